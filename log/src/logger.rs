@@ -54,10 +54,14 @@ impl AdvancedLogger {
     }
 
     pub fn init(log_level: LogLevel) -> Result<(), LogError> {
-        let logger = Arc::new(AdvancedLogger::new(
+        Self::init_with_file(
             log_level,
-            Some(data_dir().unwrap().join("vista").join("latest.log")),
-        ));
+            data_dir().unwrap().join("vista").join("latest.log"),
+        )
+    }
+
+    pub fn init_with_file(log_level: LogLevel, log_file: PathBuf) -> Result<(), LogError> {
+        let logger = Arc::new(AdvancedLogger::new(log_level, Some(log_file)));
 
         set_logger(logger)?;
 
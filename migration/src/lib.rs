@@ -0,0 +1,18 @@
+pub use sea_orm_migration::prelude::*;
+
+mod m20261017_000001_create_event_tables;
+mod m20261017_000002_add_crossing_label;
+mod m20261017_000003_create_occupancy_tables;
+
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20261017_000001_create_event_tables::Migration),
+            Box::new(m20261017_000002_add_crossing_label::Migration),
+            Box::new(m20261017_000003_create_occupancy_tables::Migration),
+        ]
+    }
+}
@@ -0,0 +1,156 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Crossings::Table)
+                    .if_not_exists()
+                    .col(pk_auto(Crossings::Id))
+                    .col(integer(Crossings::ObjectId))
+                    .col(string(Crossings::Direction))
+                    .col(integer(Crossings::X))
+                    .col(integer(Crossings::Y))
+                    .col(integer(Crossings::Width))
+                    .col(integer(Crossings::Height))
+                    .col(timestamp_with_time_zone(Crossings::Time))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_crossings_time")
+                    .table(Crossings::Table)
+                    .col(Crossings::Time)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(TagReads::Table)
+                    .if_not_exists()
+                    .col(pk_auto(TagReads::Id))
+                    .col(string(TagReads::Tag))
+                    .col(integer(TagReads::Antenna))
+                    .col(integer(TagReads::Rssi))
+                    .col(timestamp_with_time_zone(TagReads::Time))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_tag_reads_time")
+                    .table(TagReads::Table)
+                    .col(TagReads::Time)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Entries::Table)
+                    .if_not_exists()
+                    .col(pk_auto(Entries::Id))
+                    .col(string_uniq(Entries::EventId))
+                    .col(string(Entries::Kind))
+                    .col(string_null(Entries::Tag))
+                    .col(string_null(Entries::Direction))
+                    .col(timestamp_with_time_zone(Entries::Time))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_entries_time")
+                    .table(Entries::Table)
+                    .col(Entries::Time)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Deliveries::Table)
+                    .if_not_exists()
+                    .col(pk_auto(Deliveries::Id))
+                    .col(string_uniq(Deliveries::EventId))
+                    .col(string(Deliveries::Status))
+                    .col(string_null(Deliveries::EntryId))
+                    .col(string_null(Deliveries::Reason))
+                    .col(timestamp_with_time_zone(Deliveries::UpdatedAt))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in [
+            Deliveries::Table.into_iden(),
+            Entries::Table.into_iden(),
+            TagReads::Table.into_iden(),
+            Crossings::Table.into_iden(),
+        ] {
+            manager
+                .drop_table(Table::drop().table(table).to_owned())
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Crossings {
+    Table,
+    Id,
+    ObjectId,
+    Direction,
+    X,
+    Y,
+    Width,
+    Height,
+    Time,
+}
+
+#[derive(DeriveIden)]
+enum TagReads {
+    Table,
+    Id,
+    Tag,
+    Antenna,
+    Rssi,
+    Time,
+}
+
+#[derive(DeriveIden)]
+enum Entries {
+    Table,
+    Id,
+    EventId,
+    Kind,
+    Tag,
+    Direction,
+    Time,
+}
+
+#[derive(DeriveIden)]
+enum Deliveries {
+    Table,
+    Id,
+    EventId,
+    Status,
+    EntryId,
+    Reason,
+    UpdatedAt,
+}
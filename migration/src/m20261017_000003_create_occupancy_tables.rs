@@ -0,0 +1,67 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(DoorCounts::Table)
+                    .if_not_exists()
+                    .col(pk_auto(DoorCounts::Id))
+                    .col(string_uniq(DoorCounts::Door))
+                    .col(big_integer(DoorCounts::Entered))
+                    .col(big_integer(DoorCounts::Exited))
+                    .col(timestamp_with_time_zone(DoorCounts::Since))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(AreaAdjustments::Table)
+                    .if_not_exists()
+                    .col(pk_auto(AreaAdjustments::Id))
+                    .col(string_uniq(AreaAdjustments::Area))
+                    .col(big_integer(AreaAdjustments::Adjustment))
+                    .col(timestamp_with_time_zone(AreaAdjustments::Since))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in [
+            AreaAdjustments::Table.into_iden(),
+            DoorCounts::Table.into_iden(),
+        ] {
+            manager
+                .drop_table(Table::drop().table(table).to_owned())
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum DoorCounts {
+    Table,
+    Id,
+    Door,
+    Entered,
+    Exited,
+    Since,
+}
+
+#[derive(DeriveIden)]
+enum AreaAdjustments {
+    Table,
+    Id,
+    Area,
+    Adjustment,
+    Since,
+}
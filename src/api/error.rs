@@ -0,0 +1,77 @@
+use reqwest::StatusCode;
+use std::fmt::Display;
+
+/// Errors returned by [`super::Api`]
+#[derive(Debug)]
+pub enum ApiError {
+    /// The request could not be sent or no response was received
+    Transport(reqwest::Error),
+    /// The server rejected the API key
+    Unauthorized(StatusCode),
+    /// The entry does not exist
+    NotFound,
+    /// The server refused the request, retrying it will not help
+    Rejected { status: StatusCode, body: String },
+    /// The server failed to handle the request
+    Server { status: StatusCode, body: String },
+    /// The request body could not be serialized
+    Encode(serde_json::Error),
+    /// The response body is not what the API documents
+    Decode(reqwest::Error),
+    /// The configured base URL cannot be used
+    InvalidUrl(String),
+}
+
+impl ApiError {
+    pub fn from_status(status: StatusCode, body: String) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ApiError::Unauthorized(status),
+            StatusCode::NOT_FOUND => ApiError::NotFound,
+            status if status.is_server_error() => ApiError::Server { status, body },
+            status => ApiError::Rejected { status, body },
+        }
+    }
+
+    /// Whether sending the same request again may succeed
+    pub fn is_transient(&self) -> bool {
+        match self {
+            // Only failing to reach the server, a request that could not be
+            // built fails the same way every time
+            ApiError::Transport(e) => e.is_timeout() || e.is_connect(),
+            ApiError::Server { .. } => true,
+            ApiError::Rejected { status, .. } => *status == StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Unauthorized(_)
+            | ApiError::NotFound
+            | ApiError::Encode(_)
+            | ApiError::Decode(_)
+            | ApiError::InvalidUrl(_) => false,
+        }
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::Transport(e) => write!(f, "Request failed: {}", e),
+            ApiError::Unauthorized(status) => write!(f, "API key rejected ({})", status),
+            ApiError::NotFound => write!(f, "Entry not found"),
+            ApiError::Rejected { status, body } => {
+                write!(f, "Request rejected ({}): {}", status, body)
+            }
+            ApiError::Server { status, body } => write!(f, "Server error ({}): {}", status, body),
+            ApiError::Encode(e) => write!(f, "Invalid request: {}", e),
+            ApiError::Decode(e) => write!(f, "Invalid response: {}", e),
+            ApiError::InvalidUrl(e) => write!(f, "Invalid API base URL {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ApiError::Transport(e) | ApiError::Decode(e) => Some(e),
+            ApiError::Encode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
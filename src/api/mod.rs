@@ -1,72 +1,268 @@
-use anyhow::Result;
-use reqwest::Response;
+use log::warning;
+use rand::Rng;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Method, RequestBuilder, Response, Url};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::sleep;
+use uuid::Uuid;
 
 use crate::auth::auth;
+use crate::auth::keyring::Keyring;
+use crate::conf::ApiConf;
 
-#[derive(Serialize, Deserialize)]
+mod error;
+pub mod queue;
+
+pub use error::ApiError;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct APIDetectionRequest {
-    person_id: String,
-    action: String,
+    /// Generated by the client so the same crossing is never recorded twice
+    pub event_id: Uuid,
+    pub person_id: String,
+    pub action: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct APIUpdateRequest {
+    pub action: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct APIDetectionResponse {
-    person_id: String,
-    action: String,
+    /// Entry id used to amend or delete the entry
+    pub id: String,
+    pub person_id: String,
+    pub action: String,
+}
+
+/// How failed requests are retried.
+///
+/// The n-th retry waits between half and all of `base_delay * 2^n`, capped at
+/// `max_delay`, so clients that failed together do not retry together.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let half = backoff / 2;
+
+        half + half.mul_f64(rand::rng().random::<f64>())
+    }
+}
+
+impl From<&ApiConf> for RetryPolicy {
+    fn from(conf: &ApiConf) -> Self {
+        Self {
+            max_retries: conf.retries,
+            base_delay: Duration::from_millis(conf.backoff_ms),
+            max_delay: Duration::from_millis(conf.max_backoff_ms),
+        }
+    }
 }
 
 struct ApiSpec {
-    base_url: String, // Changed to owned String
+    base_url: Url,
 }
 
 impl ApiSpec {
-    pub fn new(base_url: String) -> Self {
-        Self { base_url }
+    pub fn new(base_url: &str) -> Result<Self, ApiError> {
+        let base_url =
+            Url::parse(base_url).map_err(|e| ApiError::InvalidUrl(format!("{base_url}: {e}")))?;
+        if base_url.cannot_be_a_base() {
+            return Err(ApiError::InvalidUrl(format!("{base_url}: not a base URL")));
+        }
+
+        Ok(Self { base_url })
     }
 
-    pub fn post_url(&self) -> String {
-        format!("{}/api/entry", self.base_url)
+    pub fn post_url(&self) -> Url {
+        self.url(&["api", "entry"])
     }
 
-    pub fn post_hmac(&self) -> String {
-        auth::compute_hmac(&self.post_url())
+    pub fn put_url(&self, entry_id: &str) -> Url {
+        self.url(&["api", "entry", entry_id])
     }
 
-    pub fn put_url(&self, entry_id: String) -> String {
-        format!("{}/api/entry/{}", self.base_url, entry_id)
+    pub fn delete_url(&self, entry_id: &str) -> Url {
+        self.url(&["api", "entry", entry_id])
     }
 
-    pub fn delete_url(&self, entry_id: String) -> String {
-        format!("{}/api/entry/{}", self.base_url, entry_id)
+    /// `base_url` with `segments` appended to its path, each one percent-encoded
+    fn url(&self, segments: &[&str]) -> Url {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("base URLs have a path")
+            .pop_if_empty()
+            .extend(segments);
+        url
     }
 }
 
-struct Api {
+pub struct Api {
     spec: ApiSpec,
     client: reqwest::Client,
+    keyring: Keyring,
+    retry: RetryPolicy,
 }
 
 impl Api {
-    pub fn new(base_url: &str) -> Self {
-        Self {
-            spec: ApiSpec::new(base_url.to_owned()),
-            client: reqwest::Client::new(),
-        }
+    pub fn new(conf: &ApiConf, keyring: Keyring) -> Result<Self, ApiError> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(conf.timeout_ms))
+            .build()
+            .map_err(ApiError::Transport)?;
+
+        Ok(Self {
+            spec: ApiSpec::new(&conf.base_url)?,
+            client,
+            keyring,
+            retry: RetryPolicy::from(conf),
+        })
+    }
+
+    /// Creates an entry, returning it with the id the server assigned
+    pub async fn add_detection(
+        &self,
+        detection: &APIDetectionRequest,
+    ) -> Result<APIDetectionResponse, ApiError> {
+        let body = Self::encode(detection)?;
+        let response = self
+            .send(|| self.signed(Method::POST, self.spec.post_url(), &body))
+            .await?;
+
+        Self::decode(response).await
+    }
+
+    /// Changes the action of an existing entry
+    #[allow(
+        dead_code,
+        reason = "client of the PUT endpoint, nothing amends entries yet"
+    )]
+    pub async fn change_detection(
+        &self,
+        entry_id: &str,
+        action: &str,
+    ) -> Result<APIDetectionResponse, ApiError> {
+        let body = Self::encode(&APIUpdateRequest {
+            action: action.to_owned(),
+        })?;
+        let response = self
+            .send(|| self.signed(Method::PUT, self.spec.put_url(entry_id), &body))
+            .await?;
+
+        Self::decode(response).await
     }
 
-    pub async fn add_detection(&self, detection: APIDetectionRequest) -> Result<()> {
-        self.client
-            .post(self.spec.post_url())
-            .header("X-Syn-Api-Key", self.spec.post_hmac())
-            .json(&detection)
-            .send()
+    /// Deletes an entry recorded by mistake
+    #[allow(
+        dead_code,
+        reason = "client of the DELETE endpoint, nothing deletes entries yet"
+    )]
+    pub async fn delete_detection(&self, entry_id: &str) -> Result<(), ApiError> {
+        self.send(|| self.signed(Method::DELETE, self.spec.delete_url(entry_id), &[]))
             .await?;
 
         Ok(())
     }
 
-    // pub async fn change_detection() {
+    /// Builds a request carrying its signature headers, signed over the
+    /// encoded path of `url` as the server receives it.
+    ///
+    /// Every call uses a new nonce, so retries are not mistaken for replays.
+    fn signed(&self, method: Method, url: Url, body: &[u8]) -> RequestBuilder {
+        let signature = auth::sign(&self.keyring, method.as_str(), url.path(), body);
+
+        let mut request = self
+            .client
+            .request(method, url)
+            .header(auth::KEY_ID_HEADER, signature.key_id)
+            .header(auth::API_KEY_HEADER, signature.api_key)
+            .header(auth::TIMESTAMP_HEADER, signature.timestamp)
+            .header(auth::NONCE_HEADER, signature.nonce);
+        if !body.is_empty() {
+            request = request
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.to_vec());
+        }
+
+        request
+    }
 
-    // }
+    /// Sends the request built by `request`, retrying transient failures
+    /// according to the retry policy
+    async fn send<F>(&self, request: F) -> Result<Response, ApiError>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let mut attempt = 0;
+
+        loop {
+            let result = match request().send().await {
+                Ok(response) => Self::check(response).await,
+                Err(e) => Err(ApiError::Transport(e)),
+            };
+
+            match result {
+                Err(e) if e.is_transient() && attempt < self.retry.max_retries => {
+                    let delay = self.retry.delay(attempt);
+                    warning!("{}, retrying in {:?}", e, delay);
+                    sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn check(response: Response) -> Result<Response, ApiError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        Err(ApiError::from_status(status, body))
+    }
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, ApiError> {
+        serde_json::to_vec(value).map_err(ApiError::Encode)
+    }
+
+    async fn decode<T: DeserializeOwned>(response: Response) -> Result<T, ApiError> {
+        response.json().await.map_err(ApiError::Decode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_urls_keep_the_base_path() {
+        let spec = ApiSpec::new("http://host:8080/attendance/").unwrap();
+        assert_eq!(
+            spec.post_url().as_str(),
+            "http://host:8080/attendance/api/entry"
+        );
+
+        let spec = ApiSpec::new("http://host/ä b").unwrap();
+        assert_eq!(
+            spec.put_url("7/1").as_str(),
+            "http://host/%C3%A4%20b/api/entry/7%2F1"
+        );
+        assert_eq!(spec.delete_url("7").path(), "/%C3%A4%20b/api/entry/7");
+
+        assert!(ApiSpec::new("host:8080").is_err());
+    }
 }
@@ -0,0 +1,593 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use dirs::data_dir;
+use log::{debug, error, info, warning};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, mpsc};
+use tokio::task;
+use tokio::time::{interval, timeout};
+use uuid::Uuid;
+
+use super::{APIDetectionRequest, Api, ApiError};
+use crate::db::{DeliveryStatus, EventStore};
+
+/// Records a compaction would drop past which the journal is compacted
+const COMPACT_AFTER: usize = 1000;
+/// Days the id of a delivered or rejected event is remembered, so the same
+/// event is not queued again
+const KEEP_SETTLED_DAYS: i64 = 7;
+/// Longest the last delivery attempt may take on shutdown, whatever is left
+/// stays in the journal for the next run
+const FINAL_DRAIN: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record {
+    /// A request waiting to be delivered
+    Enqueue { request: APIDetectionRequest },
+    /// The server created entry `entry_id` for the request
+    Ack {
+        event_id: Uuid,
+        entry_id: String,
+        at: DateTime<Utc>,
+    },
+    /// The server refused the request, it will never be sent again
+    Reject {
+        event_id: Uuid,
+        reason: String,
+        at: DateTime<Utc>,
+    },
+    /// Delivered or rejected before the last compaction, kept for
+    /// [`KEEP_SETTLED_DAYS`] so the event is not queued again
+    Settled { event_id: Uuid, at: DateTime<Utc> },
+}
+
+/// Write-ahead queue of API submissions.
+///
+/// Every change is appended to a JSON lines journal and synced to disk before
+/// it takes effect, so requests survive restarts and power loss. Requests are
+/// delivered in the order they were queued and an event id still in the
+/// journal is never queued twice.
+pub struct Queue {
+    path: PathBuf,
+    journal: File,
+    pending: VecDeque<APIDetectionRequest>,
+    /// Every event id in the journal, with when it was settled
+    seen: HashMap<Uuid, Option<DateTime<Utc>>>,
+    records: usize,
+}
+
+impl Queue {
+    /// Journal location next to the log files
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(data_dir()
+            .context("No data directory for this user")?
+            .join("vista")
+            .join("api_queue.jsonl"))
+    }
+
+    /// Opens the journal at `path`, creating it if needed, and restores the
+    /// requests that were not delivered yet
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+
+        // A record without its newline was cut short by a crash, drop it so
+        // the next record starts on a line of its own
+        let complete = content.rfind('\n').map_or(0, |i| i + 1);
+        if complete < content.len() {
+            warning!(
+                "Dropping {} bytes of a partially written record in {}",
+                content.len() - complete,
+                path.display()
+            );
+        }
+
+        let mut pending = VecDeque::new();
+        let mut seen = HashMap::new();
+        let mut records = 0;
+
+        for (number, line) in content[..complete].lines().enumerate() {
+            let record = match serde_json::from_str::<Record>(line) {
+                Ok(record) => record,
+                Err(e) => {
+                    warning!("Skipping invalid record on line {}: {}", number + 1, e);
+                    continue;
+                }
+            };
+            records += 1;
+
+            match record {
+                Record::Enqueue { request } => {
+                    if let Entry::Vacant(entry) = seen.entry(request.event_id) {
+                        entry.insert(None);
+                        pending.push_back(request);
+                    }
+                }
+                Record::Ack { event_id, at, .. }
+                | Record::Reject { event_id, at, .. }
+                | Record::Settled { event_id, at } => {
+                    seen.insert(event_id, Some(at));
+                    pending.retain(|r| r.event_id != event_id);
+                }
+            }
+        }
+
+        let journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        journal.set_len(complete as u64)?;
+
+        if !pending.is_empty() {
+            info!("Restored {} undelivered API requests", pending.len());
+        }
+
+        Ok(Self {
+            path: path.to_owned(),
+            journal,
+            pending,
+            seen,
+            records,
+        })
+    }
+
+    /// Queues `request`, returning false if its event id was already queued
+    pub fn push(&mut self, request: APIDetectionRequest) -> Result<bool> {
+        if self.seen.contains_key(&request.event_id) {
+            debug!("Ignoring duplicate event {}", request.event_id);
+            return Ok(false);
+        }
+
+        self.append(&Record::Enqueue {
+            request: request.clone(),
+        })?;
+        self.seen.insert(request.event_id, None);
+        self.pending.push_back(request);
+
+        Ok(true)
+    }
+
+    /// Oldest request not delivered yet
+    pub fn front(&self) -> Option<&APIDetectionRequest> {
+        self.pending.front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Marks `event_id` as delivered
+    pub fn ack(&mut self, event_id: Uuid, entry_id: &str) -> Result<()> {
+        let at = Utc::now();
+        self.append(&Record::Ack {
+            event_id,
+            entry_id: entry_id.to_owned(),
+            at,
+        })?;
+        self.settle(event_id, at)
+    }
+
+    /// Gives up on `event_id`
+    pub fn reject(&mut self, event_id: Uuid, reason: &str) -> Result<()> {
+        let at = Utc::now();
+        self.append(&Record::Reject {
+            event_id,
+            reason: reason.to_owned(),
+            at,
+        })?;
+        self.settle(event_id, at)
+    }
+
+    fn settle(&mut self, event_id: Uuid, at: DateTime<Utc>) -> Result<()> {
+        self.pending.retain(|r| r.event_id != event_id);
+        self.seen.insert(event_id, Some(at));
+
+        // Compaction keeps one record per event id
+        if self.records.saturating_sub(self.seen.len()) > COMPACT_AFTER {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the journal with the event ids settled in the last
+    /// [`KEEP_SETTLED_DAYS`] and the pending requests, forgetting older ones.
+    ///
+    /// The new journal is synced before it replaces the old one, so a crash at
+    /// any point leaves one of the two complete journals in place.
+    pub fn compact(&mut self) -> Result<()> {
+        let cutoff = Utc::now() - TimeDelta::days(KEEP_SETTLED_DAYS);
+        self.seen
+            .retain(|_, settled| settled.is_none_or(|at| at >= cutoff));
+
+        let tmp = self.path.with_extension("jsonl.tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp)?);
+            let settled = self.seen.iter().filter_map(|(event_id, settled)| {
+                settled.map(|at| Record::Settled {
+                    event_id: *event_id,
+                    at,
+                })
+            });
+            let records = settled.chain(self.pending.iter().map(|request| Record::Enqueue {
+                request: request.clone(),
+            }));
+            for record in records {
+                serde_json::to_writer(&mut writer, &record)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.into_inner()?.sync_all()?;
+        }
+
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace {}", self.path.display()))?;
+        if let Some(dir) = self.path.parent()
+            && !dir.as_os_str().is_empty()
+        {
+            File::open(dir)?.sync_all()?;
+        }
+
+        self.journal = OpenOptions::new().append(true).open(&self.path)?;
+        debug!(
+            "Compacted API queue from {} to {} records",
+            self.records,
+            self.seen.len()
+        );
+        self.records = self.seen.len();
+
+        Ok(())
+    }
+
+    fn append(&mut self, record: &Record) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        self.journal
+            .write_all(&line)
+            .and_then(|_| self.journal.sync_data())
+            .with_context(|| format!("Failed to write to {}", self.path.display()))?;
+        self.records += 1;
+
+        Ok(())
+    }
+}
+
+/// Shares `queue` between the code producing requests and [`deliver`]
+pub fn channel(queue: Queue, store: Option<EventStore>) -> (QueueSender, QueueReceiver) {
+    let queue = Arc::new(Mutex::new(queue));
+    let (wake_tx, wake_rx) = mpsc::channel(1);
+
+    (
+        QueueSender {
+            queue: Arc::clone(&queue),
+            wake: wake_tx,
+            store: store.clone(),
+        },
+        QueueReceiver {
+            queue,
+            wake: wake_rx,
+            store,
+        },
+    )
+}
+
+/// Queues requests for [`deliver`]
+#[derive(Clone)]
+pub struct QueueSender {
+    queue: Arc<Mutex<Queue>>,
+    wake: mpsc::Sender<()>,
+    store: Option<EventStore>,
+}
+
+impl QueueSender {
+    /// Queues `request`, returning false if its event id was already queued.
+    ///
+    /// The request is in the journal once this returns, delivered or not.
+    pub async fn push(&self, request: APIDetectionRequest) -> Result<bool> {
+        let event_id = request.event_id;
+        if !write(&self.queue, move |queue| queue.push(request)).await? {
+            return Ok(false);
+        }
+
+        // A full channel already holds a wake-up
+        let _ = self.wake.try_send(());
+        track(
+            self.store.as_ref(),
+            event_id,
+            DeliveryStatus::Queued,
+            None,
+            None,
+        )
+        .await;
+
+        Ok(true)
+    }
+}
+
+/// Delivery side of [`channel`], closed once every [`QueueSender`] is dropped
+pub struct QueueReceiver {
+    queue: Arc<Mutex<Queue>>,
+    wake: mpsc::Receiver<()>,
+    store: Option<EventStore>,
+}
+
+/// Runs `change` on the blocking pool, as it waits for the journal to reach
+/// the disk
+async fn write<T, F>(queue: &Arc<Mutex<Queue>>, change: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut Queue) -> Result<T> + Send + 'static,
+{
+    let mut queue = Arc::clone(queue).lock_owned().await;
+    task::spawn_blocking(move || change(&mut queue)).await?
+}
+
+/// Sends queued requests in order until the queue is empty or the API is
+/// unreachable, returning how many were delivered
+pub async fn drain(
+    queue: &Arc<Mutex<Queue>>,
+    api: &Api,
+    store: Option<&EventStore>,
+) -> Result<usize> {
+    let mut delivered = 0;
+
+    loop {
+        let Some(request) = queue.lock().await.front().cloned() else {
+            break;
+        };
+        let event_id = request.event_id;
+
+        match api.add_detection(&request).await {
+            Ok(response) => {
+                let entry_id = response.id.clone();
+                write(queue, move |queue| queue.ack(event_id, &entry_id)).await?;
+                track(
+                    store,
+                    event_id,
+                    DeliveryStatus::Delivered,
+                    Some(&response.id),
+                    None,
+                )
+                .await;
+                delivered += 1;
+            }
+            // The entry was created, there is just no id to remember
+            Err(ApiError::Decode(e)) => {
+                warning!("Entry for event {} created: {}", event_id, e);
+                write(queue, move |queue| queue.ack(event_id, "")).await?;
+                track(store, event_id, DeliveryStatus::Delivered, None, None).await;
+                delivered += 1;
+            }
+            Err(e @ (ApiError::NotFound | ApiError::Rejected { .. })) => {
+                error!("Dropping event {}: {}", event_id, e);
+                let reason = e.to_string();
+                let rejected = reason.clone();
+                write(queue, move |queue| queue.reject(event_id, &rejected)).await?;
+                track(
+                    store,
+                    event_id,
+                    DeliveryStatus::Rejected,
+                    None,
+                    Some(&reason),
+                )
+                .await;
+            }
+            Err(e) => {
+                let queued = queue.lock().await.len();
+                warning!("API unavailable, {} requests queued: {}", queued, e);
+                break;
+            }
+        }
+    }
+
+    Ok(delivered)
+}
+
+/// Delivers the requests queued through `receiver`, retrying every
+/// `retry_every` while the API is unreachable.
+///
+/// Returns once every [`QueueSender`] is dropped, after one last delivery
+/// attempt of at most [`FINAL_DRAIN`].
+pub async fn deliver(mut receiver: QueueReceiver, api: Api, retry_every: Duration) {
+    let queue = &receiver.queue;
+    let store = receiver.store.as_ref();
+    let mut retry = interval(retry_every);
+    // Once the API is unreachable new requests wait for the next retry
+    let mut offline = false;
+
+    loop {
+        tokio::select! {
+            wake = receiver.wake.recv() => match wake {
+                None => break,
+                Some(()) if offline => continue,
+                Some(()) => {}
+            },
+            _ = retry.tick() => {
+                if queue.lock().await.is_empty() {
+                    continue;
+                }
+            }
+        }
+
+        tokio::select! {
+            result = drain(queue, &api, store) => {
+                if let Err(e) = result {
+                    error!("Failed to update API queue: {:#}", e);
+                }
+            }
+            // Stop waiting on retries, the last attempt below is bounded
+            _ = closed(&mut receiver.wake) => break,
+        }
+        offline = !queue.lock().await.is_empty();
+    }
+
+    match timeout(FINAL_DRAIN, drain(queue, &api, store)).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => error!("Failed to update API queue: {:#}", e),
+        Err(_) => warning!("API delivery cut short by shutdown"),
+    }
+
+    let left = queue.lock().await.len();
+    if left > 0 {
+        info!("{} API requests left for the next run", left);
+    }
+}
+
+/// Waits for every sender of `wake` to be dropped
+async fn closed(wake: &mut mpsc::Receiver<()>) {
+    while wake.recv().await.is_some() {}
+}
+
+/// Records the delivery state of `event_id` in the event store, if there is one
+async fn track(
+    store: Option<&EventStore>,
+    event_id: Uuid,
+    status: DeliveryStatus,
+    entry_id: Option<&str>,
+    reason: Option<&str>,
+) {
+    if let Some(store) = store
+        && let Err(e) = store
+            .record_delivery(event_id, status, entry_id, reason)
+            .await
+    {
+        warning!("Failed to store delivery of event {}: {:#}", event_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn journal(name: &str) -> PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("vista-queue-{}", std::process::id()))
+            .join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn request(person_id: &str) -> APIDetectionRequest {
+        APIDetectionRequest {
+            event_id: Uuid::new_v4(),
+            person_id: person_id.into(),
+            action: "entered".into(),
+        }
+    }
+
+    #[test]
+    fn test_pending_requests_survive_reopen() {
+        let path = journal("reopen.jsonl");
+        let (first, second) = (request("a"), request("b"));
+
+        let mut queue = Queue::open(&path).unwrap();
+        assert!(queue.push(first.clone()).unwrap());
+        assert!(queue.push(second.clone()).unwrap());
+        assert!(!queue.push(first.clone()).unwrap());
+        queue.ack(first.event_id, "1").unwrap();
+        drop(queue);
+
+        let mut queue = Queue::open(&path).unwrap();
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.front().unwrap().event_id, second.event_id);
+        assert!(!queue.push(first).unwrap());
+    }
+
+    #[test]
+    fn test_partial_record_is_dropped() {
+        let path = journal("partial.jsonl");
+        let kept = request("a");
+
+        let mut queue = Queue::open(&path).unwrap();
+        queue.push(kept.clone()).unwrap();
+        drop(queue);
+
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"op\":\"enq").unwrap();
+        drop(file);
+
+        let mut queue = Queue::open(&path).unwrap();
+        assert_eq!(queue.len(), 1);
+        queue.push(request("b")).unwrap();
+        drop(queue);
+
+        assert_eq!(Queue::open(&path).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_compaction_keeps_settled_event_ids() {
+        let path = journal("compact.jsonl");
+        let (delivered, pending) = (request("a"), request("b"));
+
+        let mut queue = Queue::open(&path).unwrap();
+        queue.push(delivered.clone()).unwrap();
+        queue.push(pending.clone()).unwrap();
+        queue.ack(delivered.event_id, "1").unwrap();
+        queue.compact().unwrap();
+        drop(queue);
+
+        let mut queue = Queue::open(&path).unwrap();
+        assert_eq!(queue.len(), 1);
+        assert!(!queue.push(delivered).unwrap());
+        assert!(!queue.push(pending).unwrap());
+    }
+
+    #[test]
+    fn test_compaction_forgets_old_settled_event_ids() {
+        let path = journal("expire.jsonl");
+        let (old, recent) = (request("a"), request("b"));
+
+        let mut journal = File::create(&path).unwrap();
+        for (event_id, days) in [(old.event_id, KEEP_SETTLED_DAYS + 1), (recent.event_id, 1)] {
+            let record = Record::Settled {
+                event_id,
+                at: Utc::now() - TimeDelta::days(days),
+            };
+            writeln!(journal, "{}", serde_json::to_string(&record).unwrap()).unwrap();
+        }
+        drop(journal);
+
+        let mut queue = Queue::open(&path).unwrap();
+        queue.compact().unwrap();
+        drop(queue);
+
+        let mut queue = Queue::open(&path).unwrap();
+        assert!(!queue.push(recent).unwrap());
+        assert!(queue.push(old).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_pushed_request_is_journaled_before_delivery() {
+        let path = journal("sender.jsonl");
+        let queued = request("a");
+
+        let (sender, receiver) = channel(Queue::open(&path).unwrap(), None);
+        assert!(sender.push(queued.clone()).await.unwrap());
+        // Crash before the delivery task ever ran
+        drop((sender, receiver));
+
+        let queue = Queue::open(&path).unwrap();
+        assert_eq!(queue.front().unwrap().event_id, queued.event_id);
+    }
+}
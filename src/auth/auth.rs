@@ -1,27 +1,285 @@
 use hex::encode;
 use hmac::{Hmac, Mac};
-use once_cell::sync::Lazy;
-use sha2::Sha256;
-use std::env::var;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-static API_SECRET: Lazy<String> =
-    Lazy::new(|| var("SYN_API_SECRET").unwrap_or_else(|_| "secret".to_string()));
+use super::keyring::Keyring;
 
-pub fn verify_api_key(api_key: &str, uri: &str) -> bool {
-    let expected_hmac = compute_hmac(uri);
+type HmacSha256 = Hmac<Sha256>;
 
-    expected_hmac == api_key
+pub const KEY_ID_HEADER: &str = "X-Syn-Key-Id";
+pub const API_KEY_HEADER: &str = "X-Syn-Api-Key";
+pub const TIMESTAMP_HEADER: &str = "X-Syn-Timestamp";
+pub const NONCE_HEADER: &str = "X-Syn-Nonce";
+
+/// Largest difference in seconds between the signer's and the verifier's clocks
+pub const DEFAULT_SKEW_SECS: u64 = 300;
+
+/// Headers sent with every signed request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub key_id: String,
+    pub api_key: String,
+    pub timestamp: u64,
+    pub nonce: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuthError {
+    /// The key id is not in the keyring
+    UnknownKey,
+    /// The timestamp is further from the verifier's clock than the skew allows
+    Expired,
+    /// The nonce was already used by an accepted request
+    Replayed,
+    /// The API key does not match the request
+    InvalidSignature,
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::UnknownKey => write!(f, "Unknown API key id"),
+            AuthError::Expired => write!(f, "Request timestamp outside the allowed window"),
+            AuthError::Replayed => write!(f, "Request nonce already used"),
+            AuthError::InvalidSignature => write!(f, "Invalid API key"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Signs a request to `path` sent now with a fresh nonce and the active key
+pub fn sign(keyring: &Keyring, method: &str, path: &str, body: &[u8]) -> Signature {
+    let (key_id, secret) = keyring.active();
+    let timestamp = unix_now();
+    let nonce = encode(rand::rng().random::<[u8; 16]>());
+
+    Signature {
+        key_id: key_id.to_owned(),
+        api_key: compute_hmac(secret, method, path, timestamp, &nonce, body),
+        timestamp,
+        nonce,
+    }
 }
 
-pub fn compute_hmac(uri: &str) -> String {
-    type HmacSha256 = Hmac<Sha256>;
+/// HMAC of the method, path, timestamp, nonce and SHA-256 of the body, one
+/// per line
+pub fn compute_hmac(
+    secret: &str,
+    method: &str,
+    path: &str,
+    timestamp: u64,
+    nonce: &str,
+    body: &[u8],
+) -> String {
+    encode(
+        mac(secret, method, path, timestamp, nonce, body)
+            .finalize()
+            .into_bytes(),
+    )
+}
 
+fn mac(
+    secret: &str,
+    method: &str,
+    path: &str,
+    timestamp: u64,
+    nonce: &str,
+    body: &[u8],
+) -> HmacSha256 {
     let mut mac =
-        HmacSha256::new_from_slice(API_SECRET.as_bytes()).expect("HMAC can take key of any size");
-    mac.update(uri.as_bytes());
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(
+        format!(
+            "{}\n{}\n{}\n{}\n{}",
+            method.to_uppercase(),
+            path,
+            timestamp,
+            nonce,
+            encode(Sha256::digest(body))
+        )
+        .as_bytes(),
+    );
+
+    mac
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Verifies signed requests, remembering the nonces it accepted for as long
+/// as their timestamp is inside the skew window
+pub struct Verifier {
+    keyring: Keyring,
+    skew: u64,
+    nonces: Mutex<HashMap<String, u64>>,
+}
+
+impl Verifier {
+    pub fn new(keyring: Keyring, skew_secs: u64) -> Self {
+        Self {
+            keyring,
+            skew: skew_secs,
+            nonces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn verify_api_key(
+        &self,
+        method: &str,
+        path: &str,
+        body: &[u8],
+        signature: &Signature,
+    ) -> Result<(), AuthError> {
+        self.verify_at(method, path, body, signature, unix_now())
+    }
+
+    fn verify_at(
+        &self,
+        method: &str,
+        path: &str,
+        body: &[u8],
+        signature: &Signature,
+        now: u64,
+    ) -> Result<(), AuthError> {
+        if signature.timestamp.abs_diff(now) > self.skew {
+            return Err(AuthError::Expired);
+        }
+
+        let secret = self
+            .keyring
+            .secret(&signature.key_id)
+            .ok_or(AuthError::UnknownKey)?;
+        let expected = hex::decode(&signature.api_key).map_err(|_| AuthError::InvalidSignature)?;
+        mac(
+            secret,
+            method,
+            path,
+            signature.timestamp,
+            &signature.nonce,
+            body,
+        )
+        .verify_slice(&expected)
+        .map_err(|_| AuthError::InvalidSignature)?;
+
+        let mut nonces = self.nonces.lock().unwrap();
+        // Nonces older than the window are rejected by their timestamp already
+        let skew = self.skew;
+        nonces.retain(|_, timestamp| timestamp.abs_diff(now) <= skew);
+
+        if nonces.contains_key(&signature.nonce) {
+            return Err(AuthError::Replayed);
+        }
+        nonces.insert(signature.nonce.clone(), signature.timestamp);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    const BODY: &[u8] = br#"{"person_id":"E200","action":"entered"}"#;
+
+    fn keyring(active_key: &str, keys: &[(&str, &str)]) -> Keyring {
+        let keys = keys
+            .iter()
+            .map(|(id, secret)| (id.to_string(), secret.to_string()))
+            .collect::<BTreeMap<_, _>>();
+        Keyring::new(active_key.into(), keys).unwrap()
+    }
+
+    #[test]
+    fn test_signed_request_is_accepted_once() {
+        let keyring = keyring("a", &[("a", "alpha")]);
+        let verifier = Verifier::new(keyring.clone(), DEFAULT_SKEW_SECS);
+        let signature = sign(&keyring, "POST", "/api/entry", BODY);
+
+        assert_eq!(
+            verifier.verify_api_key("POST", "/api/entry", BODY, &signature),
+            Ok(())
+        );
+        assert_eq!(
+            verifier.verify_api_key("POST", "/api/entry", BODY, &signature),
+            Err(AuthError::Replayed)
+        );
+    }
+
+    #[test]
+    fn test_tampered_request_is_rejected() {
+        let keyring = keyring("a", &[("a", "alpha")]);
+        let verifier = Verifier::new(keyring.clone(), DEFAULT_SKEW_SECS);
+        let signature = sign(&keyring, "POST", "/api/entry", BODY);
+
+        for (method, path, body) in [
+            ("PUT", "/api/entry", BODY),
+            ("POST", "/api/entry/1", BODY),
+            ("POST", "/api/entry", &b"{}"[..]),
+        ] {
+            assert_eq!(
+                verifier.verify_api_key(method, path, body, &signature),
+                Err(AuthError::InvalidSignature)
+            );
+        }
+    }
+
+    #[test]
+    fn test_stale_request_is_rejected() {
+        let keyring = keyring("a", &[("a", "alpha")]);
+        let verifier = Verifier::new(keyring, DEFAULT_SKEW_SECS);
+        let timestamp = 1_700_000_000;
+        let signature = Signature {
+            key_id: "a".into(),
+            api_key: compute_hmac("alpha", "DELETE", "/api/entry/1", timestamp, "abc", b""),
+            timestamp,
+            nonce: "abc".into(),
+        };
+
+        assert_eq!(
+            verifier.verify_at("DELETE", "/api/entry/1", b"", &signature, timestamp + 301),
+            Err(AuthError::Expired)
+        );
+        assert_eq!(
+            verifier.verify_at("DELETE", "/api/entry/1", b"", &signature, timestamp + 10),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_rotated_keys_are_verified_by_id() {
+        let old = keyring("old", &[("old", "alpha")]);
+        let rotated = keyring("new", &[("old", "alpha"), ("new", "beta")]);
+        let verifier = Verifier::new(rotated.clone(), DEFAULT_SKEW_SECS);
+
+        let signature = sign(&old, "POST", "/api/entry", BODY);
+        assert_eq!(
+            verifier.verify_api_key("POST", "/api/entry", BODY, &signature),
+            Ok(())
+        );
 
-    let result = mac.finalize();
-    let code_bytes = result.into_bytes();
+        let signature = sign(&rotated, "POST", "/api/entry", BODY);
+        assert_eq!(signature.key_id, "new");
+        assert_eq!(
+            verifier.verify_api_key("POST", "/api/entry", BODY, &signature),
+            Ok(())
+        );
 
-    encode(code_bytes)
+        let retired = Verifier::new(keyring("new", &[("new", "beta")]), DEFAULT_SKEW_SECS);
+        let signature = sign(&old, "POST", "/api/entry", BODY);
+        assert_eq!(
+            retired.verify_api_key("POST", "/api/entry", BODY, &signature),
+            Err(AuthError::UnknownKey)
+        );
+    }
 }
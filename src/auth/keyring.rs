@@ -0,0 +1,102 @@
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env::var;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::conf::{AuthConf, redacted_keys};
+
+/// Secret used when nothing is configured, only good for local testing
+pub const DEFAULT_SECRET: &str = "secret";
+const DEFAULT_KEY_ID: &str = "default";
+
+/// Named API secrets.
+///
+/// Requests are signed with the active key and verified with whichever key
+/// they name, so a new key can be rolled out to servers before clients start
+/// using it, and an old one removed once no client signs with it anymore.
+#[derive(Clone, Deserialize)]
+pub struct Keyring {
+    pub active_key: String,
+    pub keys: BTreeMap<String, String>,
+}
+
+impl fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keyring")
+            .field("active_key", &self.active_key)
+            .field("keys", &redacted_keys(&self.keys))
+            .finish()
+    }
+}
+
+impl Keyring {
+    pub fn new(active_key: String, keys: BTreeMap<String, String>) -> Result<Self> {
+        let keyring = Self { active_key, keys };
+        keyring.validate()?;
+        Ok(keyring)
+    }
+
+    /// Reads a YAML keyring with `active_key` and `keys` entries
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read keyring {}", path.display()))?;
+        let keyring: Self = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse keyring {}", path.display()))?;
+        keyring.validate()?;
+        Ok(keyring)
+    }
+
+    /// Builds the keyring from the keyring file, the keys in the config or
+    /// `SYN_API_SECRET`, whichever is found first.
+    ///
+    /// Falls back to [`DEFAULT_SECRET`], which is refused unless
+    /// `allow_insecure` is set.
+    pub fn from_conf(conf: &AuthConf) -> Result<Self> {
+        let keyring = if let Some(path) = &conf.keyring {
+            Self::from_file(path)?
+        } else if !conf.keys.is_empty() {
+            Self::new(conf.active_key.clone(), conf.keys.clone())?
+        } else {
+            let secret = var("SYN_API_SECRET").unwrap_or_else(|_| DEFAULT_SECRET.to_string());
+            Self::new(
+                DEFAULT_KEY_ID.into(),
+                BTreeMap::from([(DEFAULT_KEY_ID.to_string(), secret)]),
+            )?
+        };
+
+        if keyring.is_insecure() && !conf.allow_insecure {
+            bail!(
+                "Refusing to use the default API secret, configure a keyring or set auth.allow_insecure"
+            );
+        }
+
+        Ok(keyring)
+    }
+
+    /// Id and secret of the key new requests are signed with
+    pub fn active(&self) -> (&str, &str) {
+        (&self.active_key, &self.keys[&self.active_key])
+    }
+
+    /// Secret of `key_id` if requests signed with it are accepted
+    pub fn secret(&self, key_id: &str) -> Option<&str> {
+        self.keys.get(key_id).map(String::as_str)
+    }
+
+    pub fn is_insecure(&self) -> bool {
+        self.keys.values().any(|secret| secret == DEFAULT_SECRET)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !self.keys.contains_key(&self.active_key) {
+            bail!("Active key {:?} is not in the keyring", self.active_key);
+        }
+        if let Some((id, _)) = self.keys.iter().find(|(_, secret)| secret.is_empty()) {
+            bail!("Key {:?} has an empty secret", id);
+        }
+        Ok(())
+    }
+}
@@ -1 +1,3 @@
+#[allow(clippy::module_inception)]
 pub mod auth;
+pub mod keyring;
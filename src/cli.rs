@@ -1,43 +1,183 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use crate::cv::tracker::TrackerBackend;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// Output debug information
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// Allow the default API secret, for local testing only
+    #[arg(long, global = true)]
+    pub insecure: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Count door crossings from the camera
+    Run(RunArgs),
+    /// Record video and RFID reads with synchronized timestamps
+    Record(RecordArgs),
+    /// Process a session captured with `record`
+    Replay(ReplayArgs),
+    /// Interactively set up the counting line or zones
+    Calibrate(CalibrateArgs),
+    /// Serve the entry API locally, for sites without the central backend
+    Serve(ServeArgs),
+    /// Check the camera, model files, RFID spool and API
+    Doctor,
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Inspect or correct the people counts of doors and areas
+    Occupancy {
+        #[command(subcommand)]
+        action: OccupancyCommand,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ModelArgs {
+    /// Default confidence value for detections
+    #[arg(short, long)]
+    pub default_confidence: Option<f32>,
+
+    /// Frames to wait between detections
+    #[arg(short, long)]
+    pub step: Option<u8>,
+
+    /// Network description (Caffe prototxt, TensorFlow pbtxt, Darknet cfg)
+    #[arg(short, long)]
+    pub proto: Option<PathBuf>,
+
+    /// Network weights (caffemodel, pb, weights or onnx)
+    #[arg(short, long)]
+    pub model: Option<PathBuf>,
+
+    /// Tracker run between detections: kcf, csrt, mosse, mil or none
+    #[arg(long)]
+    pub tracker: Option<TrackerBackend>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct RunArgs {
     /// Read a file instead of using the camera
     #[arg(short, long)]
     pub input: Option<String>,
 
-    /// Output debug information
+    /// Run without a window, stopping on SIGINT or SIGTERM
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Write the rendered overlay to this video file
     #[arg(short, long)]
-    pub verbose: bool,
+    pub overlay_output: Option<PathBuf>,
 
-    /// Output file for processed data
+    #[command(flatten)]
+    pub model: ModelArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct RecordArgs {
+    /// Camera to record from
     #[arg(short, long)]
-    pub write_data: bool,
+    pub input: Option<String>,
 
-    /// Default confidence value for detections
-    #[arg(short, long, default_value_t = 0.1)]
-    pub default_confidence: f32,
+    /// Directory to write the video, frame timestamps and RFID reads to
+    #[arg(short, long)]
+    pub output_dir: Option<PathBuf>,
+}
 
-    /// Frames to wait between detections
-    #[arg(short, long, default_value_t = 10)]
-    pub step: u8,
-
-    ///  Caffe 'deploy' prototxt file
-    #[arg(
-        short,
-        long,
-        default_value = "mobilenet_ssd/MobileNetSSD_deploy.prototxt"
-    )]
-    pub proto: String,
-
-    /// Caffe model
-    #[arg(
-        short,
-        long,
-        default_value = "mobilenet_ssd/MobileNetSSD_deploy.caffemodel"
-    )]
-    pub model: String,
+#[derive(clap::Args, Debug)]
+pub struct ReplayArgs {
+    /// Directory holding a recorded session, defaults to the recorder output paths
+    #[arg(long)]
+    pub session_dir: Option<PathBuf>,
+
+    /// Maximum time in milliseconds between a tag read and a crossing for them to be linked
+    #[arg(long)]
+    pub fusion_window_ms: Option<u64>,
+
+    /// Write the fused events to this CSV file
+    #[arg(short, long)]
+    pub events: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub model: ModelArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CalibrateArgs {
+    /// Read a file instead of using the camera
+    #[arg(short, long)]
+    pub input: Option<String>,
+
+    /// Draw entry and exit polygons instead of a counting line
+    #[arg(short, long)]
+    pub zones: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ServeArgs {
+    /// Address to listen on
+    #[arg(short, long)]
+    pub bind: Option<String>,
+
+    /// JSON file to store entries in
+    #[arg(short, long)]
+    pub store: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the effective configuration after applying the file, environment and flags
+    Show,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum OccupancyCommand {
+    /// Print the counts of every door and area since the last reset
+    Show,
+    /// Set the number of people inside an area, until the next reset
+    Set {
+        /// Area name from the occupancy configuration
+        area: String,
+        /// People inside right now
+        #[arg(allow_negative_numbers = true)]
+        inside: i64,
+    },
+    /// Start every count over from zero
+    Reset,
+}
+
+/// Exit codes reported by every subcommand.
+///
+/// Clap already uses 2 for usage errors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exit {
+    Success = 0,
+    Failure = 1,
+    Config = 3,
+    Camera = 4,
+    Model = 5,
+    Rfid = 6,
+    Api = 7,
+    Database = 8,
+}
+
+impl From<Exit> for ExitCode {
+    fn from(exit: Exit) -> Self {
+        ExitCode::from(exit as u8)
+    }
 }
 
 pub fn parse_args() -> Args {
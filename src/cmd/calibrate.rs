@@ -0,0 +1,225 @@
+use log::{debug, error, info, warning};
+use opencv::core::{Mat, MatTraitConst, Point, Scalar};
+use opencv::imgproc::{HersheyFonts, LineTypes};
+use opencv::videoio::{CAP_PROP_POS_FRAMES, VideoCaptureTrait};
+use opencv::{highgui, imgproc};
+use std::sync::{Arc, Mutex};
+
+use crate::cli::Exit;
+use crate::conf::{Conf, store_counting};
+use crate::cv::geometry::{CountingGeometry, NormPoint};
+use crate::cv::{get_stream_camera, init_window};
+
+#[derive(Default)]
+struct Calibration {
+    /// Line points, or entry polygon points when calibrating zones
+    entry: Vec<Point>,
+    /// Exit polygon points
+    exit: Vec<Point>,
+    editing_exit: bool,
+}
+
+impl Calibration {
+    fn click(&mut self, point: Point, zones: bool) {
+        if zones {
+            if self.editing_exit {
+                self.exit.push(point);
+            } else {
+                self.entry.push(point);
+            }
+        } else {
+            // A third click starts a new line
+            if self.entry.len() >= 2 {
+                self.entry.clear();
+            }
+            self.entry.push(point);
+        }
+    }
+
+    fn undo(&mut self) {
+        if self.editing_exit {
+            self.exit.pop();
+        } else {
+            self.entry.pop();
+        }
+    }
+
+    fn geometry(&self, zones: bool, width: i32, height: i32) -> Option<CountingGeometry> {
+        let normalize =
+            |p: &Point| -> NormPoint { [p.x as f32 / width as f32, p.y as f32 / height as f32] };
+
+        if zones {
+            if self.entry.len() < 3 || self.exit.len() < 3 {
+                return None;
+            }
+            Some(CountingGeometry::Zones {
+                entry: self.entry.iter().map(normalize).collect(),
+                exit: self.exit.iter().map(normalize).collect(),
+            })
+        } else {
+            match self.entry.as_slice() {
+                [start, end] => Some(CountingGeometry::Line {
+                    start: normalize(start),
+                    end: normalize(end),
+                }),
+                _ => None,
+            }
+        }
+    }
+
+    fn draw_points(&self, frame: &mut Mat) -> opencv::Result<()> {
+        for (points, color) in [
+            (&self.entry, Scalar::new(0., 255., 0., 0.)),
+            (&self.exit, Scalar::new(0., 0., 255., 0.)),
+        ] {
+            for point in points {
+                imgproc::circle(frame, *point, 4, color, -1, imgproc::LINE_8, 0)?;
+            }
+            for pair in points.windows(2) {
+                imgproc::line(frame, pair[0], pair[1], color, 1, imgproc::LINE_8, 0)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Interactive counting line or zone setup.
+///
+/// Left click adds a point, right click removes the last one. With zones, `n`
+/// switches between the entry and the exit polygon. `s` saves the geometry to
+/// the config file, `c` clears it and `q` quits.
+pub fn calibrate(cfg: &Conf, zones: bool) -> Exit {
+    let mut stream = match get_stream_camera(&cfg.camera.device) {
+        Ok(stream) => stream,
+        Err(e) => {
+            error!("Failed to open camera stream: {}", e);
+            return Exit::Camera;
+        }
+    };
+
+    let win_name = init_window();
+    let state = Arc::new(Mutex::new(Calibration::default()));
+
+    let callback_state = Arc::clone(&state);
+    let callback = move |event: i32, x: i32, y: i32, _flags: i32| {
+        let Ok(mut state) = callback_state.lock() else {
+            return;
+        };
+        if event == highgui::EVENT_LBUTTONDOWN {
+            state.click(Point::new(x, y), zones);
+        } else if event == highgui::EVENT_RBUTTONDOWN {
+            state.undo();
+        }
+    };
+    if let Err(e) = highgui::set_mouse_callback(win_name, Some(Box::new(callback))) {
+        error!("Failed to register mouse callback: {}", e);
+        return Exit::Failure;
+    }
+
+    let help = if zones {
+        "click: add point | n: entry/exit | s: save | c: clear | q: quit"
+    } else {
+        "click: line point | s: save | c: clear | q: quit"
+    };
+
+    let mut frame = Mat::default();
+    let mut exit = Exit::Success;
+
+    loop {
+        match stream.read(&mut frame) {
+            Ok(true) if !frame.empty() => {}
+            Ok(_) => {
+                // Loop video files so there is always something to draw on
+                debug!("End of stream, rewinding");
+                if let Err(e) = stream.set(CAP_PROP_POS_FRAMES, 0.) {
+                    error!("Failed to rewind input: {}", e);
+                    exit = Exit::Camera;
+                    break;
+                }
+                continue;
+            }
+            Err(e) => {
+                error!("Failed to read from camera: {}", e);
+                exit = Exit::Camera;
+                break;
+            }
+        }
+
+        let (width, height) = (frame.cols(), frame.rows());
+        let mut canvas = frame.clone();
+        let geometry = {
+            let state = state.lock().unwrap();
+            if let Err(e) = state.draw_points(&mut canvas) {
+                warning!("Failed to draw calibration points: {}", e);
+            }
+            state.geometry(zones, width, height)
+        };
+
+        if let Some(geometry) = &geometry
+            && let Err(e) = geometry.draw(&mut canvas)
+        {
+            warning!("Failed to draw counting geometry: {}", e);
+        }
+
+        if let Err(e) = imgproc::put_text(
+            &mut canvas,
+            help,
+            Point::new(10, 30),
+            HersheyFonts::FONT_HERSHEY_SIMPLEX.into(),
+            0.6,
+            Scalar::new(255.0, 255.0, 255.0, 0.0),
+            1,
+            LineTypes::LINE_AA.into(),
+            false,
+        ) {
+            warning!("Failed to add help text: {}", e);
+        }
+
+        if let Err(e) = highgui::imshow(win_name, &canvas) {
+            error!("Failed to display frame: {}", e);
+            exit = Exit::Failure;
+            break;
+        }
+
+        let key = match highgui::wait_key(30) {
+            Ok(key) => key,
+            Err(e) => {
+                error!("Failed to poll keyboard: {}", e);
+                exit = Exit::Failure;
+                break;
+            }
+        };
+
+        match u8::try_from(key).map(char::from) {
+            Ok('q') | Ok('\u{1b}') => {
+                info!("Calibration cancelled");
+                break;
+            }
+            Ok('c') => *state.lock().unwrap() = Calibration::default(),
+            Ok('n') if zones => {
+                let mut state = state.lock().unwrap();
+                state.editing_exit = !state.editing_exit;
+            }
+            Ok('s') => {
+                let Some(geometry) = geometry else {
+                    warning!("Counting geometry is not complete yet");
+                    continue;
+                };
+                if let Err(e) = geometry.validate().and_then(|_| store_counting(&geometry)) {
+                    error!("Failed to save counting geometry: {:#}", e);
+                    exit = Exit::Config;
+                } else {
+                    info!("Saved counting geometry: {:?}", geometry);
+                }
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    if let Err(e) = highgui::destroy_all_windows() {
+        warning!("Failed to clean up windows: {}", e);
+    }
+
+    exit
+}
@@ -0,0 +1,128 @@
+use anyhow::{Context, Result, bail};
+use opencv::core::{Mat, MatTraitConst};
+use opencv::videoio::{VideoCaptureTrait, VideoCaptureTraitConst};
+use sea_orm::ConnectionTrait;
+use std::fs::OpenOptions;
+use std::time::Duration;
+
+use crate::cli::Exit;
+use crate::conf::Conf;
+use crate::cv::reid::Describer;
+use crate::cv::{detector, get_stream_camera};
+use crate::db::{default_db_path, pending_migrations};
+
+/// Checks every external dependency and prints a report.
+///
+/// Exits with the code of the first failed check.
+pub fn doctor(cfg: &Conf) -> Exit {
+    let checks = [
+        ("camera", Exit::Camera, check_camera(cfg)),
+        ("model", Exit::Model, check_model(cfg)),
+        ("rfid", Exit::Rfid, check_rfid(cfg)),
+        ("api", Exit::Api, check_api(cfg)),
+        ("database", Exit::Database, check_database(cfg)),
+    ];
+
+    let mut exit = Exit::Success;
+    for (name, code, result) in checks {
+        match result {
+            Ok(detail) => println!("[ OK ] {name}: {detail}"),
+            Err(e) => {
+                println!("[FAIL] {name}: {e:#}");
+                if exit == Exit::Success {
+                    exit = code;
+                }
+            }
+        }
+    }
+
+    exit
+}
+
+fn check_camera(cfg: &Conf) -> Result<String> {
+    let mut camera = get_stream_camera(&cfg.camera.device)
+        .with_context(|| format!("Failed to open {}", cfg.camera.device))?;
+    if !camera.is_opened()? {
+        bail!("{} could not be opened", cfg.camera.device);
+    }
+
+    let mut frame = Mat::default();
+    if !camera.read(&mut frame)? || frame.empty() {
+        bail!("{} returned no frame", cfg.camera.device);
+    }
+
+    Ok(format!(
+        "{} ({}x{})",
+        cfg.camera.device,
+        frame.cols(),
+        frame.rows()
+    ))
+}
+
+fn check_model(cfg: &Conf) -> Result<String> {
+    for file in [&cfg.model.proto, &cfg.model.weights] {
+        if !file.as_os_str().is_empty() && !file.is_file() {
+            bail!("{} does not exist", file.display());
+        }
+    }
+
+    detector::open(&cfg.model).context("Failed to load network")?;
+    Describer::new(&cfg.reid)?;
+
+    Ok(format!("{}", cfg.model.weights.display()))
+}
+
+fn check_rfid(cfg: &Conf) -> Result<String> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&cfg.rfid.spool)
+        .with_context(|| format!("Cannot open spool {}", cfg.rfid.spool.display()))?;
+
+    let lock = std::fs::read_to_string(&cfg.rfid.read_lock)
+        .with_context(|| format!("Cannot read lock {}", cfg.rfid.read_lock.display()))?;
+
+    Ok(format!(
+        "{} (reading: {})",
+        cfg.rfid.spool.display(),
+        lock.trim() == "1"
+    ))
+}
+
+fn check_api(cfg: &Conf) -> Result<String> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()?;
+
+    let response = runtime
+        .block_on(client.get(&cfg.api.base_url).send())
+        .with_context(|| format!("{} is unreachable", cfg.api.base_url))?;
+
+    Ok(format!("{} (HTTP {})", cfg.api.base_url, response.status()))
+}
+
+/// Connects to the database without creating or migrating it
+fn check_database(cfg: &Conf) -> Result<String> {
+    if cfg.db_conn.is_empty() {
+        let path = default_db_path()?;
+        if !path.is_file() {
+            return Ok(format!("{} (created on the first run)", path.display()));
+        }
+    }
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let (db, pending) = runtime.block_on(pending_migrations(&cfg.db_conn))?;
+
+    let backend = db.get_database_backend();
+    if pending.is_empty() {
+        Ok(format!("{:?}", backend))
+    } else {
+        Ok(format!(
+            "{:?} ({} migrations applied on the next run: {})",
+            backend,
+            pending.len(),
+            pending.join(", ")
+        ))
+    }
+}
@@ -0,0 +1,7 @@
+pub mod calibrate;
+pub mod doctor;
+pub mod occupancy;
+pub mod record;
+pub mod replay;
+pub mod run;
+pub mod serve;
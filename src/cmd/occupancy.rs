@@ -0,0 +1,74 @@
+use anyhow::Result;
+use log::error;
+
+use crate::cli::{Exit, OccupancyCommand};
+use crate::conf::Conf;
+use crate::db::EventStore;
+use crate::occupancy::{Count, Occupancy};
+
+/// Prints, corrects or resets the counts kept in the event store
+pub fn occupancy(cfg: &Conf, action: &OccupancyCommand) -> Exit {
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            error!("Failed to start async runtime: {}", e);
+            return Exit::Failure;
+        }
+    };
+
+    let store = match runtime.block_on(EventStore::open(&cfg.db_conn)) {
+        Ok(store) => store,
+        Err(e) => {
+            error!("Failed to open the event store: {:#}", e);
+            return Exit::Database;
+        }
+    };
+
+    let occupancy = match Occupancy::new(&cfg.occupancy, store) {
+        Ok(occupancy) => occupancy,
+        Err(e) => {
+            error!("Invalid occupancy settings: {:#}", e);
+            return Exit::Config;
+        }
+    };
+
+    let result = runtime.block_on(async {
+        match action {
+            OccupancyCommand::Show => {}
+            OccupancyCommand::Set { area, inside } => {
+                if !cfg.occupancy.areas.iter().any(|a| &a.name == area) {
+                    error!("Unknown area {:?}", area);
+                    return Ok(Exit::Config);
+                }
+                occupancy.correct(area, *inside).await?;
+            }
+            OccupancyCommand::Reset => occupancy.reset().await?,
+        }
+        show(&occupancy).await?;
+        Ok::<_, anyhow::Error>(Exit::Success)
+    });
+
+    result.unwrap_or_else(|e| {
+        error!("Failed to update the counts: {:#}", e);
+        Exit::Database
+    })
+}
+
+async fn show(occupancy: &Occupancy) -> Result<()> {
+    let snapshot = occupancy.snapshot().await?;
+    let print = |kind: &str, name: &str, count: &Count| {
+        println!(
+            "{kind} {name}: {} inside, {} entered, {} exited",
+            count.inside, count.entered, count.exited
+        );
+    };
+
+    for (name, count) in &snapshot.doors {
+        print("door", name, count);
+    }
+    for (name, count) in &snapshot.areas {
+        print("area", name, count);
+    }
+
+    Ok(())
+}
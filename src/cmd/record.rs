@@ -0,0 +1,43 @@
+use log::{error, info};
+
+use crate::cli::Exit;
+use crate::conf::Conf;
+use crate::recorder::{SynchronizedRecorder, SynchronizedRecorderConfig};
+
+/// Records video and RFID reads until CTRL+C
+pub fn record(cfg: &Conf) -> Exit {
+    if !cfg.rfid.spool.exists() {
+        error!("RFID spool {} does not exist", cfg.rfid.spool.display());
+        return Exit::Rfid;
+    }
+
+    let recorder_config = SynchronizedRecorderConfig {
+        camera_path: cfg.camera.device.clone().into(),
+        rfid_path: cfg.rfid.spool.clone(),
+        read_lock: cfg.rfid.read_lock.clone(),
+        output_video: cfg.recorder.output_video.clone(),
+        output_video_timestamps: cfg.recorder.output_video_timestamps.clone(),
+        output_detections: cfg.recorder.output_detections.clone(),
+        duty_cycle: cfg.rfid.duty_cycle_ms,
+    };
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            error!("Failed to start async runtime: {}", e);
+            return Exit::Failure;
+        }
+    };
+
+    let recorder = SynchronizedRecorder::new(recorder_config);
+    match runtime.block_on(recorder.start()) {
+        Ok(_) => {
+            info!("Recording finished");
+            Exit::Success
+        }
+        Err(e) => {
+            error!("Recording failed: {:#}", e);
+            Exit::Failure
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use log::{error, info};
+use regex::Regex;
+use std::path::Path;
+
+use crate::cli::Exit;
+use crate::conf::Conf;
+use crate::cv::get_stream_camera;
+use crate::cv::net::Net;
+use crate::proc::FusionConfig;
+use crate::replay::{Replay, Session};
+
+/// Runs a recorded session through the network and the fusion engine with
+/// its recorded timestamps, optionally writing the fused events to `events`
+pub fn replay(cfg: &Conf, events: Option<&Path>) -> Exit {
+    let recorder = &cfg.recorder;
+    info!("Replaying {}", recorder.output_video.display());
+
+    let session = match Session::load(
+        &recorder.output_video_timestamps,
+        &recorder.output_detections,
+    ) {
+        Ok(session) => session,
+        Err(e) => {
+            error!("Failed to load recorded session: {:#}", e);
+            return Exit::Failure;
+        }
+    };
+    info!(
+        "Loaded {} frame timestamps and {} RFID reads",
+        session.frames(),
+        session.reads()
+    );
+
+    let tag_re = match Regex::new(&cfg.rfid.tag_pattern) {
+        Ok(re) => re,
+        Err(e) => {
+            error!("Invalid RFID tag pattern: {}", e);
+            return Exit::Config;
+        }
+    };
+
+    let mut stream = match get_stream_camera(&recorder.output_video.to_string_lossy()) {
+        Ok(stream) => stream,
+        Err(e) => {
+            error!("Failed to open recorded video: {}", e);
+            return Exit::Camera;
+        }
+    };
+
+    let mut net = match Net::new(
+        &cfg.model,
+        &cfg.preprocess,
+        &cfg.filter,
+        &cfg.tracker,
+        &cfg.reid,
+        cfg.counting.clone(),
+        &cfg.crossing,
+    ) {
+        Ok(net) => net,
+        Err(e) => {
+            error!("Failed to load neural network model: {:#}", e);
+            return Exit::Model;
+        }
+    };
+
+    let fusion = FusionConfig::from(&cfg.fusion);
+    let replay = Replay::new(session, tag_re, cfg.rfid.target_tag.clone(), fusion);
+
+    let report = match replay.run(&mut stream, &mut net) {
+        Ok(report) => report,
+        Err(e) => {
+            error!("Replay failed: {:#}", e);
+            return Exit::Failure;
+        }
+    };
+
+    for (timestamp, event) in &report.events {
+        info!("{}: {:?}", timestamp, event);
+    }
+
+    let (entered, exited) = report.tally();
+    info!(
+        "Replayed {} frames: {} entered, {} exited",
+        report.frames, entered, exited
+    );
+    for (label, entered, exited) in report.classes.iter() {
+        info!("{}: {} entered, {} exited", label, entered, exited);
+    }
+
+    if let Some(path) = events {
+        if let Err(e) = report.write_csv(path) {
+            error!("Failed to write events: {:#}", e);
+            return Exit::Failure;
+        }
+        info!("Wrote {} events to {}", report.events.len(), path.display());
+    }
+
+    Exit::Success
+}
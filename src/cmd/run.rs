@@ -0,0 +1,470 @@
+use anyhow::Result;
+use log::{critical, debug, error, info, warning};
+use opencv::core::{Mat, MatTraitConst, Point, Scalar};
+use opencv::imgproc::{HersheyFonts, LineTypes};
+use opencv::videoio::{
+    CAP_PROP_FPS, VideoCapture, VideoCaptureTrait, VideoCaptureTraitConst, VideoWriter,
+    VideoWriterTrait,
+};
+use opencv::{highgui, imgproc};
+use regex::Regex;
+use std::env::var_os;
+use std::future::pending;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+#[cfg(debug_assertions)]
+use std::time::Instant;
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::api::queue::{self, Queue, deliver};
+use crate::api::{APIDetectionRequest, Api};
+use crate::auth::keyring::Keyring;
+use crate::cli::Exit;
+use crate::cmd::serve::open_server;
+use crate::conf::Conf;
+use crate::cv::frame_metrics::FrameMetrics;
+use crate::cv::net::Net;
+use crate::cv::{get_stream_camera, init_window};
+use crate::db::EventStore;
+use crate::occupancy::Occupancy;
+use crate::proc::{FusedEvent, FusionConfig, proc_detections};
+use crate::rfid;
+use crate::server;
+
+/// Live counting from the configured camera
+pub fn run(cfg: &Conf) -> Exit {
+    #[cfg(debug_assertions)]
+    let start_time = Instant::now();
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            error!("Failed to start async runtime: {}", e);
+            return Exit::Failure;
+        }
+    };
+
+    let tag_re = match Regex::new(&cfg.rfid.tag_pattern) {
+        Ok(re) => re,
+        Err(e) => {
+            error!("Invalid RFID tag pattern: {}", e);
+            return Exit::Config;
+        }
+    };
+
+    let mut stream = match get_stream_camera(&cfg.camera.device) {
+        Ok(stream) => {
+            info!("Camera stream opened successfully");
+            stream
+        }
+        Err(e) => {
+            critical!("Failed to open camera stream: {}", e);
+            return Exit::Camera;
+        }
+    };
+
+    debug!("Loading neural network model...");
+    let mut net = match Net::new(
+        &cfg.model,
+        &cfg.preprocess,
+        &cfg.filter,
+        &cfg.tracker,
+        &cfg.reid,
+        cfg.counting.clone(),
+        &cfg.crossing,
+    ) {
+        Ok(net) => net,
+        Err(e) => {
+            error!("Failed to load neural network model: {:#}", e);
+            return Exit::Model;
+        }
+    };
+
+    let store = match runtime.block_on(EventStore::open(&cfg.db_conn)) {
+        Ok(store) => store,
+        Err(e) => {
+            error!("Failed to open the event store: {:#}", e);
+            return Exit::Database;
+        }
+    };
+
+    let occupancy = match Occupancy::new(&cfg.occupancy, store.clone()) {
+        Ok(occupancy) => occupancy,
+        Err(e) => {
+            error!("Invalid occupancy settings: {:#}", e);
+            return Exit::Config;
+        }
+    };
+
+    let (cv_tx, cv_rx) = mpsc::channel(64);
+    let (rfid_tx, rfid_rx) = mpsc::channel(64);
+    let (events_tx, mut events_rx) = mpsc::channel(64);
+    let fusion_config = FusionConfig::from(&cfg.fusion);
+    net.set_detection_sender(cv_tx);
+
+    let spool_handle = runtime.spawn(rfid::process_spool(
+        cfg.rfid.spool.clone(),
+        tag_re,
+        cfg.rfid.target_tag.clone(),
+        rfid_tx,
+        1000.0 / cfg.rfid.duty_cycle_ms.max(1) as f64,
+    ));
+    let fusion_handle = runtime.spawn(proc_detections(
+        rfid_rx,
+        cv_rx,
+        fusion_config,
+        events_tx,
+        Some(store.clone()),
+        Some(occupancy),
+    ));
+
+    let (api_queue, delivery_handle) = if cfg.api.enabled {
+        let keyring = match Keyring::from_conf(&cfg.auth) {
+            Ok(keyring) => keyring,
+            Err(e) => {
+                error!("Invalid API keys: {:#}", e);
+                return Exit::Config;
+            }
+        };
+        if keyring.is_insecure() {
+            warning!("Signing API requests with the default secret");
+        }
+
+        let (sender, delivery) = match open_delivery(cfg, keyring) {
+            Ok((queue, api)) => {
+                let (sender, receiver) = queue::channel(queue, Some(store.clone()));
+                let retry_every = Duration::from_millis(cfg.api.max_backoff_ms.max(1));
+                (sender, deliver(receiver, api, retry_every))
+            }
+            Err(e) => {
+                error!("Failed to set up API delivery: {:#}", e);
+                return Exit::Api;
+            }
+        };
+        (Some(sender), Some(runtime.spawn(delivery)))
+    } else {
+        (None, None)
+    };
+
+    if cfg.server.enabled {
+        let (keyring, store) = match open_server(cfg) {
+            Ok(server) => server,
+            Err(e) => {
+                error!("Failed to set up the server: {:#}", e);
+                return Exit::Config;
+            }
+        };
+        let bind = cfg.server.bind.clone();
+        runtime.spawn(async move {
+            if let Err(e) = server::serve(&bind, keyring, store).await {
+                error!("{:#}", e);
+            }
+        });
+    }
+
+    let events_handle = runtime.spawn(async move {
+        while let Some(event) = events_rx.recv().await {
+            debug!("Fused event: {:?}", event);
+
+            let event_id = Uuid::new_v4();
+            if let Err(e) = store.record_entry(event_id, &event).await {
+                warning!("Failed to store fused event: {:#}", e);
+            }
+
+            if let (Some(api_queue), FusedEvent::Person { tag, direction, .. }) =
+                (&api_queue, event)
+                && let Err(e) = api_queue
+                    .push(APIDetectionRequest {
+                        event_id,
+                        person_id: tag,
+                        action: direction.as_action().into(),
+                    })
+                    .await
+            {
+                error!("Failed to queue API request: {:#}", e);
+            }
+        }
+    });
+
+    let stop = Arc::new(AtomicBool::new(false));
+    runtime.spawn(wait_for_shutdown(Arc::clone(&stop)));
+
+    let headless = cfg.display.headless || !has_display();
+    if headless && !cfg.display.headless {
+        warning!("No display found, running headless");
+    }
+    let win_name = if headless {
+        info!("Running headless");
+        None
+    } else {
+        debug!("Initializing display window");
+        Some(init_window())
+    };
+
+    let overlay_path = cfg.display.overlay_output.as_deref();
+    let mut overlay: Option<VideoWriter> = None;
+
+    let mut fps = FrameMetrics::new();
+    let mut frame = Mat::default();
+    let mut exit = Exit::Success;
+
+    #[cfg(debug_assertions)]
+    let mut frame_count = 0;
+    #[cfg(debug_assertions)]
+    let processing_start = Instant::now();
+
+    info!("Starting main processing loop");
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            info!("Shutdown requested");
+            break;
+        }
+
+        #[cfg(debug_assertions)]
+        let frame_start = Instant::now();
+        #[cfg(debug_assertions)]
+        {
+            frame_count += 1;
+        }
+        #[cfg(debug_assertions)]
+        debug!("Capturing frame #{}...", frame_count);
+
+        match stream.read(&mut frame) {
+            Ok(true) if !frame.empty() => {
+                #[cfg(debug_assertions)]
+                debug!("Frame captured successfully");
+            }
+            Ok(_) => {
+                if Path::new(&cfg.camera.device).is_file() {
+                    info!("End of input file");
+                } else {
+                    error!("Camera stopped delivering frames");
+                    exit = Exit::Camera;
+                }
+                break;
+            }
+            Err(e) => {
+                error!("Failed to read from camera: {}", e);
+                exit = Exit::Camera;
+                break;
+            }
+        }
+
+        fps.update();
+
+        #[cfg(debug_assertions)]
+        debug!("Processing frame with neural network");
+
+        if let Err(e) = net.process_frame(&frame) {
+            warning!("Error while processing frame: {}", e);
+        }
+
+        if win_name.is_some() || overlay_path.is_some() {
+            let rendered = match render(&net, &frame, &fps) {
+                Ok(rendered) => rendered,
+                Err(e) => {
+                    warning!("Failed to render overlay: {}", e);
+                    frame.clone()
+                }
+            };
+
+            if let Some(path) = overlay_path {
+                if overlay.is_none() {
+                    match open_overlay(path, &stream, &rendered) {
+                        Ok(writer) => overlay = Some(writer),
+                        Err(e) => {
+                            error!("Failed to open overlay output {}: {}", path.display(), e);
+                            exit = Exit::Failure;
+                            break;
+                        }
+                    }
+                }
+                if let Some(writer) = overlay.as_mut()
+                    && let Err(e) = writer.write(&rendered)
+                {
+                    warning!("Failed to write overlay frame: {}", e);
+                }
+            }
+
+            if let Some(win_name) = win_name {
+                #[cfg(debug_assertions)]
+                debug!("Displaying processed frame");
+
+                if let Err(e) = highgui::imshow(win_name, &rendered) {
+                    error!("Failed to display frame: {}", e);
+                    exit = Exit::Failure;
+                    break;
+                }
+
+                // Check for exit key
+                match highgui::wait_key(10) {
+                    Ok(key) if key >= 0 => {
+                        info!("User requested exit (key: {})", key);
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        error!("Failed to poll keyboard: {}", e);
+                        exit = Exit::Failure;
+                        break;
+                    }
+                }
+            }
+        }
+
+        #[cfg(debug_assertions)]
+        if frame_count % 100 == 0 {
+            let total_time = processing_start.elapsed();
+            info!(
+                "Processed {} frames in {:.1} seconds (avg {:.1} FPS, current {:.1} FPS)",
+                frame_count,
+                total_time.as_secs_f32(),
+                frame_count as f32 / total_time.as_secs_f32(),
+                fps.get_fps()
+            );
+        }
+
+        #[cfg(debug_assertions)]
+        debug!(
+            "Frame #{} processed in {:?}",
+            frame_count,
+            frame_start.elapsed()
+        );
+    }
+
+    #[cfg(debug_assertions)]
+    {
+        let total_runtime = start_time.elapsed();
+        info!("Application shutting down after {} frames", frame_count);
+        info!("Total runtime: {:.2} seconds", total_runtime.as_secs_f32());
+        info!(
+            "Average performance: {:.1} FPS",
+            frame_count as f32 / total_runtime.as_secs_f32()
+        );
+    }
+
+    if let Some(mut writer) = overlay
+        && let Err(e) = writer.release()
+    {
+        warning!("Failed to close overlay output: {}", e);
+    }
+
+    if win_name.is_some() {
+        debug!("Destroying all windows");
+        if let Err(e) = highgui::destroy_all_windows() {
+            warning!("Failed to clean up windows: {}", e);
+        }
+    }
+
+    // Dropping the network and stopping the spool reader close the
+    // detection channels so fusion can flush
+    drop(net);
+    spool_handle.abort();
+    debug!("Waiting for detection fusion to finish");
+    runtime.block_on(async {
+        if let Err(e) = fusion_handle.await {
+            error!("Detection fusion task failed: {}", e);
+        }
+        if let Err(e) = events_handle.await {
+            error!("Fused event task failed: {}", e);
+        }
+        if let Some(delivery_handle) = delivery_handle
+            && let Err(e) = delivery_handle.await
+        {
+            error!("API delivery task failed: {}", e);
+        }
+    });
+
+    info!("Live counting stopped");
+    exit
+}
+
+fn open_delivery(cfg: &Conf, keyring: Keyring) -> Result<(Queue, Api)> {
+    let path = match &cfg.api.queue {
+        Some(path) => path.clone(),
+        None => Queue::default_path()?,
+    };
+    info!("Queueing API requests in {}", path.display());
+
+    Ok((Queue::open(&path)?, Api::new(&cfg.api, keyring)?))
+}
+
+fn has_display() -> bool {
+    var_os("DISPLAY").is_some() || var_os("WAYLAND_DISPLAY").is_some()
+}
+
+async fn wait_for_shutdown(stop: Arc<AtomicBool>) {
+    // SIGINT still stops the pipeline when SIGTERM cannot be listened for
+    let sigterm = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => sigterm.recv().await,
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                pending().await
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => info!("Received SIGINT"),
+        _ = sigterm => info!("Received SIGTERM"),
+    }
+
+    stop.store(true, Ordering::Relaxed);
+}
+
+/// Draws the tracking overlay and frame metrics on a copy of `frame`
+fn render(net: &Net, frame: &Mat, fps: &FrameMetrics) -> Result<Mat> {
+    let mut out = frame.clone();
+    net.draw_tracking_results(&mut out)?;
+
+    let fps_text = format!(
+        "FPS: {:.1} FPS | FT {:.1}ms",
+        fps.get_fps().round(),
+        fps.get_last_frame_time().as_millis()
+    );
+    let metrics_text = format!(
+        "Avg: {:.1} | Min: {:.1} | Max: {:.1} FPS",
+        fps.get_avg_fps(),
+        fps.get_min_fps(),
+        fps.get_max_fps()
+    );
+
+    let mut lines = vec![fps_text, metrics_text];
+    for (label, entered, exited) in net.tallies().iter() {
+        lines.push(format!("{}: {} in | {} out", label, entered, exited));
+    }
+
+    for (text, y) in lines.iter().zip((30..).step_by(30)) {
+        if let Err(e) = imgproc::put_text(
+            &mut out,
+            text,
+            Point::new(10, y),
+            HersheyFonts::FONT_HERSHEY_SIMPLEX.into(),
+            0.6,
+            Scalar::new(255.0, 255.0, 255.0, 0.0),
+            1,
+            LineTypes::LINE_AA.into(),
+            false,
+        ) {
+            warning!("Failed to add metrics text to frame: {}", e);
+        }
+    }
+
+    Ok(out)
+}
+
+fn open_overlay(path: &Path, stream: &VideoCapture, frame: &Mat) -> opencv::Result<VideoWriter> {
+    let fps = match stream.get(CAP_PROP_FPS)? {
+        fps if fps > 0. => fps,
+        _ => 30.,
+    };
+    let fourcc = VideoWriter::fourcc('M', 'J', 'P', 'G')?;
+    info!("Writing overlay to {}", path.display());
+
+    VideoWriter::new(&path.to_string_lossy(), fourcc, fps, frame.size()?, true)
+}
@@ -0,0 +1,59 @@
+use anyhow::Result;
+use log::{error, info, warning};
+
+use crate::auth::keyring::Keyring;
+use crate::cli::Exit;
+use crate::conf::Conf;
+use crate::server::{self, Store};
+
+/// Serves the entry API until SIGINT
+pub fn serve(cfg: &Conf) -> Exit {
+    let (keyring, store) = match open_server(cfg) {
+        Ok(server) => server,
+        Err(e) => {
+            error!("Failed to set up the server: {:#}", e);
+            return Exit::Config;
+        }
+    };
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            error!("Failed to start async runtime: {}", e);
+            return Exit::Failure;
+        }
+    };
+
+    runtime.block_on(async {
+        tokio::select! {
+            result = server::serve(&cfg.server.bind, keyring, store) => match result {
+                Ok(_) => Exit::Success,
+                Err(e) => {
+                    error!("{:#}", e);
+                    Exit::Api
+                }
+            },
+            _ = tokio::signal::ctrl_c() => {
+                info!("Received SIGINT");
+                Exit::Success
+            }
+        }
+    })
+}
+
+/// Loads the keyring and the entry store the server needs
+pub fn open_server(cfg: &Conf) -> Result<(Keyring, Store)> {
+    let keyring = Keyring::from_conf(&cfg.auth)?;
+    if keyring.is_insecure() {
+        warning!("Accepting requests signed with the default secret");
+    }
+
+    let path = match &cfg.server.store {
+        Some(path) => path.clone(),
+        None => Store::default_path()?,
+    };
+    let store = Store::open(&path)?;
+    info!("Loaded {} entries from {}", store.len(), path.display());
+
+    Ok((keyring, store))
+}
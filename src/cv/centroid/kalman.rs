@@ -0,0 +1,229 @@
+use super::Centroid;
+
+type Mat4 = [[f32; 4]; 4];
+
+/// State transition for one frame: positions advance by the velocities
+const F: Mat4 = [
+    [1., 0., 1., 0.],
+    [0., 1., 0., 1.],
+    [0., 0., 1., 0.],
+    [0., 0., 0., 1.],
+];
+
+/// Initial velocity variance, in pixels per frame squared
+const VELOCITY_VARIANCE: f32 = 100.;
+
+#[derive(Debug, Clone, Copy)]
+pub struct KalmanConfig {
+    /// Standard deviation of the acceleration, in pixels per frame squared
+    pub process_noise: f32,
+    /// Standard deviation of the detected position, in pixels
+    pub measurement_noise: f32,
+}
+
+/// Constant velocity Kalman filter over a centroid.
+///
+/// The state is `[x, y, vx, vy]` in pixels and pixels per frame.
+#[derive(Debug, Clone)]
+pub struct Kalman {
+    x: [f32; 4],
+    p: Mat4,
+    q: Mat4,
+    r: f32,
+}
+
+impl Kalman {
+    pub fn new(centroid: &Centroid, config: KalmanConfig) -> Self {
+        let r = config.measurement_noise.powi(2);
+        let a = config.process_noise.powi(2);
+
+        // Acceleration noise integrated over one frame
+        let q = [
+            [a / 4., 0., a / 2., 0.],
+            [0., a / 4., 0., a / 2.],
+            [a / 2., 0., a, 0.],
+            [0., a / 2., 0., a],
+        ];
+
+        let mut p = [[0.; 4]; 4];
+        p[0][0] = r;
+        p[1][1] = r;
+        p[2][2] = VELOCITY_VARIANCE;
+        p[3][3] = VELOCITY_VARIANCE;
+
+        Self {
+            x: [centroid.x as f32, centroid.y as f32, 0., 0.],
+            p,
+            q,
+            r,
+        }
+    }
+
+    /// Advances the state by one frame
+    pub fn predict(&mut self) {
+        let x = self.x;
+        self.x = [x[0] + x[2], x[1] + x[3], x[2], x[3]];
+
+        let fp = mul(&F, &self.p);
+        let mut p = mul(&fp, &transpose(&F));
+        for (i, row) in p.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value += self.q[i][j];
+            }
+        }
+        self.p = p;
+    }
+
+    /// Predicted position
+    pub fn position(&self) -> Centroid {
+        Centroid {
+            x: self.x[0].round() as i32,
+            y: self.x[1].round() as i32,
+        }
+    }
+
+    /// Estimated velocity in pixels per frame
+    pub fn velocity(&self) -> (f32, f32) {
+        (self.x[2], self.x[3])
+    }
+
+    /// Squared Mahalanobis distance between `centroid` and the predicted
+    /// position, follows a chi-squared distribution with 2 degrees of freedom.
+    ///
+    /// Infinite, so outside of any gate, when the distance is undefined.
+    pub fn mahalanobis2(&self, centroid: &Centroid) -> f32 {
+        let (dx, dy) = self.innovation(centroid);
+        let Some(s) = self.innovation_inverse() else {
+            return f32::INFINITY;
+        };
+
+        dx * (s[0][0] * dx + s[0][1] * dy) + dy * (s[1][0] * dx + s[1][1] * dy)
+    }
+
+    /// Updates the state with a detected position
+    pub fn correct(&mut self, centroid: &Centroid) {
+        let (dx, dy) = self.innovation(centroid);
+        let Some(s) = self.innovation_inverse() else {
+            return;
+        };
+
+        // K = P H^T S^-1, H selects the position
+        let mut k = [[0.; 2]; 4];
+        for (i, row) in k.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.p[i][0] * s[0][j] + self.p[i][1] * s[1][j];
+            }
+        }
+
+        for (i, row) in k.iter().enumerate() {
+            self.x[i] += row[0] * dx + row[1] * dy;
+        }
+
+        // P = (I - K H) P
+        let p = self.p;
+        for (i, row) in self.p.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = p[i][j] - k[i][0] * p[0][j] - k[i][1] * p[1][j];
+            }
+        }
+    }
+
+    fn innovation(&self, centroid: &Centroid) -> (f32, f32) {
+        (centroid.x as f32 - self.x[0], centroid.y as f32 - self.x[1])
+    }
+
+    /// Inverse of the innovation covariance, `None` when it is singular
+    fn innovation_inverse(&self) -> Option<[[f32; 2]; 2]> {
+        let (a, b) = (self.p[0][0] + self.r, self.p[0][1]);
+        let (c, d) = (self.p[1][0], self.p[1][1] + self.r);
+        let det = a * d - b * c;
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+
+        Some([[d / det, -b / det], [-c / det, a / det]])
+    }
+}
+
+fn mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut out = [[0.; 4]; 4];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+fn transpose(a: &Mat4) -> Mat4 {
+    let mut out = [[0.; 4]; 4];
+    for (i, row) in a.iter().enumerate() {
+        for (j, value) in row.iter().enumerate() {
+            out[j][i] = *value;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: KalmanConfig = KalmanConfig {
+        process_noise: 1.,
+        measurement_noise: 3.,
+    };
+
+    #[test]
+    fn test_learns_constant_velocity() {
+        let mut kalman = Kalman::new(&Centroid { x: 0, y: 100 }, CONFIG);
+
+        for frame in 1..=20 {
+            kalman.predict();
+            kalman.correct(&Centroid {
+                x: frame * 8,
+                y: 100 - frame * 2,
+            });
+        }
+        kalman.predict();
+
+        let (vx, vy) = kalman.velocity();
+        assert!((vx - 8.).abs() < 0.5, "vx = {vx}");
+        assert!((vy + 2.).abs() < 0.5, "vy = {vy}");
+        assert!(kalman.position().distance_to(Centroid { x: 168, y: 58 }) < 3.);
+    }
+
+    #[test]
+    fn test_gate_grows_while_unobserved() {
+        let mut kalman = Kalman::new(&Centroid { x: 0, y: 0 }, CONFIG);
+        for frame in 1..=10 {
+            kalman.predict();
+            kalman.correct(&Centroid { x: frame * 5, y: 0 });
+        }
+
+        kalman.predict();
+        let far = Centroid { x: 55, y: 40 };
+        let seen = kalman.mahalanobis2(&far);
+
+        // Three frames without a detection, the prediction keeps moving
+        for _ in 0..3 {
+            kalman.predict();
+        }
+        let ahead = Centroid { x: 70, y: 0 };
+        assert!(kalman.mahalanobis2(&ahead) < 9.21);
+        assert!(kalman.mahalanobis2(&Centroid { x: 70, y: 40 }) < seen);
+    }
+
+    #[test]
+    fn test_gate_rejects_without_measurement_noise() {
+        let config = KalmanConfig {
+            measurement_noise: 0.,
+            ..CONFIG
+        };
+        let mut kalman = Kalman::new(&Centroid { x: 0, y: 0 }, config);
+
+        assert_eq!(kalman.mahalanobis2(&Centroid { x: 0, y: 0 }), f32::INFINITY);
+        kalman.correct(&Centroid { x: 5, y: 0 });
+        assert_eq!(kalman.position(), Centroid { x: 0, y: 0 });
+    }
+}
@@ -1,15 +1,64 @@
 use anyhow::Result;
+use log::info;
 use opencv::core::Rect;
-use pathfinding::{
-    matrix::Matrix,
-    prelude::{kuhn_munkres, kuhn_munkres_min},
-};
-use rayon::prelude::*;
+use pathfinding::{matrix::Matrix, prelude::kuhn_munkres_min};
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
 
+use crate::conf::TrackerConf;
+use crate::cv::crossing::CrossingState;
+use crate::cv::geometry::iou;
+use crate::cv::reid::{self, Descriptor, Gallery};
 use crate::direction::Direction;
 
-#[derive(Debug, Clone)]
+pub mod kalman;
+
+use kalman::{Kalman, KalmanConfig};
+
+/// Cost of a pair outside the gate, larger than any sum of real costs
+const UNMATCHED: i64 = 1 << 40;
+
+/// What makes a detection the same object as a tracked one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Association {
+    /// Distance between centroids, Mahalanobis distance with the motion model
+    #[default]
+    Centroid,
+    /// Overlap of the bounding boxes
+    Iou,
+    /// Weighted sum of the box overlap and the gated centroid distance
+    Blend,
+}
+
+impl FromStr for Association {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "centroid" => Ok(Association::Centroid),
+            "iou" => Ok(Association::Iou),
+            "blend" => Ok(Association::Blend),
+            _ => Err(format!(
+                "Unknown association {s:?}, expected centroid, iou or blend"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Association {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Association::Centroid => write!(f, "centroid"),
+            Association::Iou => write!(f, "iou"),
+            Association::Blend => write!(f, "blend"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Centroid {
     pub x: i32,
     pub y: i32,
@@ -33,9 +82,39 @@ impl Centroid {
 #[derive(Debug, Clone)]
 pub struct TrackableObject {
     pub oid: u32,
+    /// Label of the class the object was detected as
+    pub label: String,
     pub centroids: Vec<Centroid>,
-    pub counted: bool,
-    pub last_direction: Option<Direction>,
+    /// Bounding boxes matched to the object, in step with `centroids`
+    pub rects: Vec<Rect>,
+    pub crossing: CrossingState,
+    /// Fitted over the last centroids, see [`crate::cv::crossing::motion`]
+    pub direction: Direction,
+    /// Normalized distance covered per frame, 0 while stationary
+    pub speed: f32,
+    /// Motion model, when the tracker predicts positions
+    pub kalman: Option<Kalman>,
+    /// Running appearance descriptor, when tracks are re-identified
+    pub appearance: Option<Descriptor>,
+}
+
+impl TrackableObject {
+    /// Last box, moved to the predicted position when there is a motion model
+    pub fn expected_rect(&self) -> Rect {
+        let rect = self.rects.last().copied().unwrap_or_default();
+        match &self.kalman {
+            Some(kalman) => {
+                let center = kalman.position();
+                Rect::new(
+                    center.x - rect.width / 2,
+                    center.y - rect.height / 2,
+                    rect.width,
+                    rect.height,
+                )
+            }
+            None => rect,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -45,115 +124,118 @@ pub struct CentroidTracker {
     disappeared: HashMap<u32, u32>,
     max_disappeared: u32,
     max_distance: f32,
+    kalman: Option<KalmanConfig>,
+    gate: f32,
+    association: Association,
+    min_iou: f32,
+    iou_weight: f32,
+    gallery: Option<Gallery>,
+    frame: u64,
 }
 
 impl CentroidTracker {
-    pub fn new(max_disappeared: u32, max_distance: f32) -> Self {
+    pub fn new(conf: &TrackerConf) -> Self {
         Self {
             next_oid: 0,
             objects: HashMap::new(),
             disappeared: HashMap::new(),
-            max_disappeared,
-            max_distance,
+            max_disappeared: conf.max_disappeared,
+            max_distance: conf.max_distance,
+            kalman: conf.kalman.then_some(KalmanConfig {
+                process_noise: conf.process_noise,
+                measurement_noise: conf.measurement_noise,
+            }),
+            gate: conf.gate,
+            association: conf.association,
+            min_iou: conf.min_iou,
+            iou_weight: conf.iou_weight.clamp(0.0, 1.0),
+            gallery: None,
+            frame: 0,
         }
     }
 
-    pub fn update(&mut self, rects: &[Rect]) -> Result<HashMap<u32, Centroid>> {
-        let input: Vec<Centroid> = rects.par_iter().map(|r| Centroid::from_rect(*r)).collect();
-
-        if self.objects.is_empty() {
-            for centroid in input {
-                self.register(centroid);
-            }
-            return Ok(self.current_centroids());
-        }
+    /// Keeps lost tracks in `gallery` so they can be re-identified
+    pub fn set_gallery(&mut self, gallery: Gallery) {
+        self.gallery = Some(gallery);
+    }
 
-        // Optimization: For small numbers of objects, use simple greedy matching
-        // instead of Hungarian algorithm which has O(n³) complexity
-        if self.objects.len() <= 5 && input.len() <= 5 {
-            return self.greedy_update(input);
+    /// Matches `rects` to the tracked objects of the same class.
+    ///
+    /// `labels` and `descriptors` hold the class and the appearance of each
+    /// rect, in the same order. `labels` is empty when a single class is
+    /// tracked, `descriptors` when appearances are not computed for this frame.
+    pub fn update(
+        &mut self,
+        rects: &[Rect],
+        labels: &[String],
+        descriptors: &[Option<Descriptor>],
+    ) -> Result<HashMap<u32, Centroid>> {
+        let input: Vec<Centroid> = rects.iter().map(|r| Centroid::from_rect(*r)).collect();
+        let label = |idx: usize| labels.get(idx).map_or("", String::as_str);
+        let descriptor = |idx: usize| descriptors.get(idx).and_then(Option::as_ref);
+
+        self.frame += 1;
+        if let Some(gallery) = &mut self.gallery {
+            gallery.expire(self.frame);
         }
 
-        // 1. Create cost matrix with max_distance check
-        let mut cost_matrix = vec![vec![0.0; input.len()]; self.objects.len()];
-        let oids: Vec<u32> = self.objects.keys().copied().collect();
-
-        for (i, oid) in oids.iter().enumerate() {
-            let obj = self.objects.get(oid).unwrap();
-            let last_centroid = obj.centroids.last().unwrap();
-
-            for (j, input_centroid) in input.iter().enumerate() {
-                let distance = last_centroid.distance_to(input_centroid.clone());
-                cost_matrix[i][j] = if distance <= self.max_distance {
-                    distance
-                } else {
-                    f32::INFINITY
-                };
+        // Objects that went unseen keep moving along their prediction, so a
+        // track survives skipped detections and short occlusions
+        for obj in self.objects.values_mut() {
+            if let Some(kalman) = &mut obj.kalman {
+                kalman.predict();
             }
         }
 
-        // 2. Convert to integer matrix with scaling and inversion for minimization
-        let int_matrix: Vec<Vec<i32>> = cost_matrix
-            .par_iter()
-            .map(|row| {
-                row.par_iter()
-                    .map(|&d| {
-                        if d > self.max_distance {
-                            i32::MAX
-                        } else {
-                            (d * 1000.0).round() as i32 // Preserve 3 decimal places
+        let oids: Vec<u32> = self.objects.keys().copied().collect();
+        let costs: Vec<Vec<Option<f32>>> = oids
+            .iter()
+            .map(|oid| {
+                let obj = &self.objects[oid];
+                rects
+                    .iter()
+                    .zip(&input)
+                    .enumerate()
+                    .map(|(idx, (rect, centroid))| {
+                        if obj.label != label(idx) {
+                            return None;
                         }
+                        self.cost(obj, *rect, centroid)
                     })
                     .collect()
             })
             .collect();
 
-        let matrix = Matrix::from_rows(int_matrix.clone())?;
-        let (total_cost, assignments) = kuhn_munkres_min(&matrix);
+        // Optimization: For small numbers of objects, use simple greedy matching
+        // instead of Hungarian algorithm which has O(n³) complexity
+        let assignments = if oids.len() <= 5 && input.len() <= 5 {
+            greedy_match(&costs)
+        } else {
+            hungarian_match(&costs)?
+        };
 
-        // 3. Process assignments
         let mut used_inputs = HashSet::new();
         let mut matched_oids = HashSet::new();
-
-        assignments
-            .par_iter()
-            .enumerate()
-            .for_each(|(obj_idx, &input_idx)| {
-                // Verify valid indices
-                if obj_idx >= oids.len() || input_idx >= input.len() {
-                    return;
-                }
-
-                let oid = oids[obj_idx];
-                let centroid = &input[input_idx];
-
-                // Check if this is a valid match (not infinite cost)
-                if int_matrix[obj_idx][input_idx] != i32::MAX {
-                    // Note: This requires additional synchronization since we're mutating shared state
-                    // Consider collecting results and applying them sequentially after the parallel loop
-                }
-            });
-
-        // Apply updates sequentially to avoid race conditions
-        for (obj_idx, &input_idx) in assignments.iter().enumerate() {
-            if obj_idx >= oids.len() || input_idx >= input.len() {
-                continue;
-            }
-
+        for (obj_idx, input_idx) in assignments {
             let oid = oids[obj_idx];
             let centroid = &input[input_idx];
 
             if let Some(obj) = self.objects.get_mut(&oid) {
-                if int_matrix[obj_idx][input_idx] != i32::MAX {
-                    obj.centroids.push(centroid.clone());
-                    self.disappeared.remove(&oid);
-                    matched_oids.insert(oid);
-                    used_inputs.insert(input_idx);
+                if let Some(kalman) = &mut obj.kalman {
+                    kalman.correct(centroid);
+                }
+                obj.centroids.push(centroid.clone());
+                obj.rects.push(rects[input_idx]);
+                if let Some(descriptor) = descriptor(input_idx) {
+                    reid::observe(&mut obj.appearance, descriptor);
                 }
+                self.disappeared.remove(&oid);
+                matched_oids.insert(oid);
+                used_inputs.insert(input_idx);
             }
         }
 
-        // 4. Handle disappeared objects
+        // Handle disappeared objects
         for oid in &oids {
             if !matched_oids.contains(oid) {
                 let entry = self.disappeared.entry(*oid).or_insert(0);
@@ -165,107 +247,332 @@ impl CentroidTracker {
             }
         }
 
-        // 5. Register new objects
-        input
-            .into_par_iter()
-            .enumerate()
-            .filter(|(input_idx, _)| !used_inputs.contains(input_idx))
-            .map(|(_, centroid)| centroid)
-            .collect::<Vec<_>>()
-            .into_iter()
-            .for_each(|centroid| self.register(centroid));
+        // Register new objects
+        for (input_idx, centroid) in input.into_iter().enumerate() {
+            if !used_inputs.contains(&input_idx) {
+                self.register(
+                    rects[input_idx],
+                    centroid,
+                    label(input_idx),
+                    descriptor(input_idx),
+                );
+            }
+        }
 
         Ok(self.current_centroids())
     }
 
-    // Optimized greedy matching for small object sets
-    fn greedy_update(&mut self, input: Vec<Centroid>) -> Result<HashMap<u32, Centroid>> {
-        let mut used_inputs = HashSet::new();
-        let mut matched_oids = HashSet::new();
-        let oids: Vec<u32> = self.objects.keys().copied().collect();
-
-        // Greedy matching: for each object, find closest input centroid
-        for oid in &oids {
-            let obj = self.objects.get(oid).unwrap();
-            let last_centroid = obj.centroids.last().unwrap();
-
-            let mut best_match: Option<usize> = None;
-            let mut best_distance = f32::INFINITY;
-
-            let (best_distance, best_match) = input
-                .par_iter()
-                .enumerate()
-                .filter(|(idx, _)| !used_inputs.contains(idx))
-                .map(|(idx, input_centroid)| {
-                    let distance = last_centroid.distance_to(input_centroid.clone());
-                    (
-                        distance,
-                        if distance <= self.max_distance {
-                            Some(idx)
-                        } else {
-                            None
-                        },
-                    )
-                })
-                .filter(|(distance, _)| *distance <= self.max_distance)
-                .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
-                .unwrap_or((f32::INFINITY, None));
-
-            if let Some(idx) = best_match {
-                if let Some(obj) = self.objects.get_mut(oid) {
-                    obj.centroids.push(input[idx].clone());
-                    self.disappeared.remove(oid);
-                    matched_oids.insert(*oid);
-                    used_inputs.insert(idx);
-                }
+    /// Cost of matching the detection `rect` to `obj`, `None` when it is
+    /// outside the gate
+    fn cost(&self, obj: &TrackableObject, rect: Rect, centroid: &Centroid) -> Option<f32> {
+        match self.association {
+            Association::Centroid => self.distance(obj, centroid).map(|(distance, _)| distance),
+            Association::Iou => {
+                let overlap = iou(obj.expected_rect(), rect);
+                (overlap >= self.min_iou).then_some(1.0 - overlap)
             }
-        }
-
-        // Handle disappeared objects
-        for oid in &oids {
-            if !matched_oids.contains(oid) {
-                let entry = self.disappeared.entry(*oid).or_insert(0);
-                *entry += 1;
-
-                if *entry > self.max_disappeared {
-                    self.deregister(*oid);
-                }
+            Association::Blend => {
+                let (_, scaled) = self.distance(obj, centroid)?;
+                let overlap = iou(obj.expected_rect(), rect);
+                Some(self.iou_weight * (1.0 - overlap) + (1.0 - self.iou_weight) * scaled)
             }
         }
+    }
 
-        // Register new objects
-        for (input_idx, centroid) in input.into_iter().enumerate() {
-            if !used_inputs.contains(&input_idx) {
-                self.register(centroid);
+    /// Distance from `obj` to `centroid` and the same distance relative to the
+    /// gate, `None` when it is outside the gate.
+    ///
+    /// With a motion model this is the squared Mahalanobis distance from the
+    /// predicted position, otherwise the distance from the last centroid.
+    fn distance(&self, obj: &TrackableObject, centroid: &Centroid) -> Option<(f32, f32)> {
+        match &obj.kalman {
+            Some(kalman) => {
+                let d2 = kalman.mahalanobis2(centroid);
+                (d2 <= self.gate).then_some((d2, d2 / self.gate))
+            }
+            None => {
+                let distance = obj.centroids.last()?.distance_to(centroid.clone());
+                (distance <= self.max_distance).then_some((distance, distance / self.max_distance))
             }
         }
-
-        Ok(self.current_centroids())
     }
 
     fn current_centroids(&self) -> HashMap<u32, Centroid> {
         self.objects
-            .par_iter()
+            .iter()
             .filter_map(|(oid, obj)| obj.centroids.last().map(|c| (*oid, c.clone())))
             .collect()
     }
 
     fn deregister(&mut self, oid: u32) {
-        self.objects.remove(&oid);
         self.disappeared.remove(&oid);
+        if let Some(obj) = self.objects.remove(&oid)
+            && let Some(gallery) = &mut self.gallery
+        {
+            gallery.insert(obj, self.frame);
+        }
     }
 
-    fn register(&mut self, centroid: Centroid) {
+    fn register(
+        &mut self,
+        rect: Rect,
+        centroid: Centroid,
+        label: &str,
+        descriptor: Option<&Descriptor>,
+    ) {
+        if let Some(descriptor) = descriptor
+            && let Some(mut obj) = self
+                .gallery
+                .as_mut()
+                .and_then(|gallery| gallery.recover(label, descriptor))
+        {
+            info!("Re-identified obj {}", obj.oid);
+            obj.kalman = self.kalman.map(|config| Kalman::new(&centroid, config));
+            obj.centroids.push(centroid);
+            obj.rects.push(rect);
+            reid::observe(&mut obj.appearance, descriptor);
+            self.objects.insert(obj.oid, obj);
+            return;
+        }
+
         let oid = self.next_oid;
         self.next_oid += 1;
         self.objects.insert(
             oid,
             TrackableObject {
                 oid,
+                label: label.to_owned(),
+                kalman: self.kalman.map(|config| Kalman::new(&centroid, config)),
                 centroids: vec![centroid],
-                counted: false,
-                last_direction: None,
+                rects: vec![rect],
+                crossing: CrossingState::default(),
+                direction: Direction::Stationary,
+                speed: 0.,
+                appearance: descriptor.cloned(),
             },
         );
     }
 }
+
+/// Pairs objects and inputs closest first, `costs` is indexed by object then input
+fn greedy_match(costs: &[Vec<Option<f32>>]) -> Vec<(usize, usize)> {
+    let mut pairs: Vec<(f32, usize, usize)> = costs
+        .iter()
+        .enumerate()
+        .flat_map(|(i, row)| {
+            row.iter()
+                .enumerate()
+                .filter_map(move |(j, cost)| cost.map(|cost| (cost, i, j)))
+        })
+        .collect();
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut used_objects = HashSet::new();
+    let mut used_inputs = HashSet::new();
+    let mut assignments = Vec::new();
+    for (_, i, j) in pairs {
+        if used_objects.contains(&i) || used_inputs.contains(&j) {
+            continue;
+        }
+        used_objects.insert(i);
+        used_inputs.insert(j);
+        assignments.push((i, j));
+    }
+
+    assignments
+}
+
+/// Minimum cost assignment of objects to inputs, ignoring gated pairs
+fn hungarian_match(costs: &[Vec<Option<f32>>]) -> Result<Vec<(usize, usize)>> {
+    let rows = costs.len();
+    let cols = costs.first().map_or(0, Vec::len);
+    if rows == 0 || cols == 0 {
+        return Ok(Vec::new());
+    }
+
+    // Pad to a square matrix, the solver needs at least as many columns as rows
+    let n = rows.max(cols);
+    let mut int_matrix = vec![vec![UNMATCHED; n]; n];
+    for (i, row) in costs.iter().enumerate() {
+        for (j, cost) in row.iter().enumerate() {
+            if let Some(cost) = cost {
+                int_matrix[i][j] = (cost * 1000.0).round() as i64; // Preserve 3 decimal places
+            }
+        }
+    }
+
+    let matrix = Matrix::from_rows(int_matrix.clone())?;
+    let (_, assignments) = kuhn_munkres_min(&matrix);
+
+    Ok(assignments
+        .into_iter()
+        .enumerate()
+        .filter(|&(i, j)| i < rows && j < cols && int_matrix[i][j] != UNMATCHED)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conf::ReidConf;
+    use crate::cv::crossing::Side;
+
+    #[test]
+    fn test_hungarian_with_more_objects_than_inputs() {
+        let costs = vec![
+            vec![Some(1.0), None],
+            vec![Some(0.5), Some(4.0)],
+            vec![None, Some(2.0)],
+        ];
+
+        let mut assignments = hungarian_match(&costs).unwrap();
+        assignments.sort();
+        assert_eq!(assignments, vec![(1, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn test_greedy_takes_closest_pair_first() {
+        let costs = vec![vec![Some(3.0), Some(1.0)], vec![Some(0.5), None]];
+
+        let mut assignments = greedy_match(&costs);
+        assignments.sort();
+        assert_eq!(assignments, vec![(0, 1), (1, 0)]);
+    }
+
+    #[test]
+    fn test_iou_keeps_side_by_side_boxes_apart() {
+        let conf = TrackerConf {
+            association: Association::Iou,
+            ..TrackerConf::default()
+        };
+        let mut tracker = CentroidTracker::new(&conf);
+
+        // A tall and a wide box with almost the same center walk past each
+        // other, the nearest centroids belong to the other box
+        let tall = Rect::new(100, 60, 20, 80);
+        let wide = Rect::new(72, 90, 80, 20);
+        tracker.update(&[tall, wide], &[], &[]).unwrap();
+        tracker
+            .update(
+                &[Rect::new(69, 90, 80, 20), Rect::new(103, 60, 20, 80)],
+                &[],
+                &[],
+            )
+            .unwrap();
+
+        for obj in tracker.objects.values() {
+            let [first, last] = obj.rects[..] else {
+                panic!("object {} has {} boxes", obj.oid, obj.rects.len());
+            };
+            assert_eq!((first.width, first.height), (last.width, last.height));
+        }
+    }
+
+    #[test]
+    fn test_lost_track_is_reidentified() {
+        let conf = TrackerConf {
+            max_disappeared: 1,
+            ..TrackerConf::default()
+        };
+        let mut tracker = CentroidTracker::new(&conf);
+        tracker.set_gallery(Gallery::new(&ReidConf::default()));
+
+        let red = Some(Descriptor::new(vec![1.0, 0.1, 0.0]));
+        let blue = Some(Descriptor::new(vec![0.0, 0.1, 1.0]));
+        tracker
+            .update(
+                &[Rect::new(10, 10, 20, 40)],
+                &[],
+                std::slice::from_ref(&red),
+            )
+            .unwrap();
+        tracker.objects.values_mut().for_each(|obj| {
+            obj.crossing.update(Some(Side::Inside), 1);
+        });
+
+        // Gone long enough to be dropped, then back somewhere else next to a stranger
+        tracker.update(&[], &[], &[]).unwrap();
+        tracker.update(&[], &[], &[]).unwrap();
+        assert!(tracker.objects.is_empty());
+        let objects = tracker
+            .update(
+                &[Rect::new(200, 10, 20, 40), Rect::new(100, 10, 20, 40)],
+                &[],
+                &[blue, red],
+            )
+            .unwrap();
+
+        assert_eq!(objects.len(), 2);
+        let back = &tracker.objects[&0];
+        assert_eq!(back.crossing.side(), Some(Side::Inside));
+        assert_eq!(back.centroids.last(), Some(&Centroid { x: 110, y: 30 }));
+    }
+
+    #[test]
+    fn test_objects_only_match_their_own_class() {
+        let mut tracker = CentroidTracker::new(&TrackerConf::default());
+        let labels = ["person".to_owned(), "bicycle".to_owned()];
+
+        tracker
+            .update(
+                &[Rect::new(100, 100, 20, 40), Rect::new(200, 100, 40, 40)],
+                &labels,
+                &[],
+            )
+            .unwrap();
+        // The bicycle moves where the person was, the person is hidden
+        tracker
+            .update(&[Rect::new(105, 100, 40, 40)], &labels[1..], &[])
+            .unwrap();
+
+        let bicycle = &tracker.objects[&2];
+        assert_eq!(bicycle.label, "bicycle");
+        assert_eq!(tracker.objects[&0].rects.len(), 1);
+        assert_eq!(tracker.objects[&1].rects.len(), 1);
+    }
+
+    #[test]
+    fn test_lost_track_is_only_reidentified_as_its_own_class() {
+        let conf = TrackerConf {
+            max_disappeared: 1,
+            ..TrackerConf::default()
+        };
+        let mut tracker = CentroidTracker::new(&conf);
+        tracker.set_gallery(Gallery::new(&ReidConf::default()));
+
+        let labels = ["person".to_owned(), "car".to_owned()];
+        let grey = Some(Descriptor::new(vec![0.5, 0.5, 0.5]));
+        tracker
+            .update(
+                &[Rect::new(10, 10, 20, 40)],
+                &labels[..1],
+                std::slice::from_ref(&grey),
+            )
+            .unwrap();
+        tracker.update(&[], &[], &[]).unwrap();
+        tracker.update(&[], &[], &[]).unwrap();
+        assert!(tracker.objects.is_empty());
+
+        // A car that looks just like the lost person
+        tracker
+            .update(
+                &[Rect::new(100, 10, 40, 20)],
+                &labels[1..],
+                std::slice::from_ref(&grey),
+            )
+            .unwrap();
+        let car = &tracker.objects[&1];
+        assert_eq!(car.label, "car");
+
+        // The person comes back as well
+        tracker
+            .update(
+                &[Rect::new(12, 10, 20, 40), Rect::new(100, 10, 40, 20)],
+                &labels,
+                &[grey.clone(), grey],
+            )
+            .unwrap();
+        assert_eq!(tracker.objects[&0].label, "person");
+        assert_eq!(tracker.objects.len(), 2);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::cv::geometry::NormPoint;
+use crate::direction::Direction;
+
+/// Where a tracked object stands relative to the door
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Outside,
+    /// Close enough to the door that the side is uncertain
+    InZone,
+    Inside,
+}
+
+/// Crossing state of one track, with hysteresis.
+///
+/// A track settles on a side once seen there. It crosses once it has stood
+/// on the other side for `min_frames` frames in a row, going back into the
+/// zone or to its settled side cancels the crossing. The settled side then
+/// flips, so the same track can later cross back the other way.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CrossingState {
+    settled: Option<Side>,
+    /// Frames in a row spent on the other side of the door
+    streak: u32,
+}
+
+impl CrossingState {
+    /// Moves the track to `side`, `None` where the door cannot be crossed.
+    ///
+    /// Returns the direction once the track has crossed.
+    pub fn update(&mut self, side: Option<Side>, min_frames: u32) -> Option<Direction> {
+        let side = match side {
+            // Going around the door is no crossing
+            None => {
+                *self = Self::default();
+                return None;
+            }
+            Some(Side::InZone) => {
+                self.streak = 0;
+                return None;
+            }
+            Some(side) => side,
+        };
+
+        if self.settled.is_none_or(|settled| settled == side) {
+            self.settled = Some(side);
+            self.streak = 0;
+            return None;
+        }
+
+        self.streak += 1;
+        if self.streak < min_frames.max(1) {
+            return None;
+        }
+
+        self.settled = Some(side);
+        self.streak = 0;
+        Some(match side {
+            Side::Inside => Direction::Up,
+            _ => Direction::Down,
+        })
+    }
+
+    pub fn side(&self) -> Option<Side> {
+        self.settled
+    }
+}
+
+/// Direction and speed of a track
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Motion {
+    pub direction: Direction,
+    /// Normalized distance covered per frame
+    pub speed: f32,
+}
+
+/// Motion over `points`, one per frame, fitted by least squares so the jitter
+/// of single positions averages out.
+///
+/// `None` with fewer than 2 points or when slower than `min_speed`.
+pub fn motion(points: &[NormPoint], min_speed: f32) -> Option<Motion> {
+    if points.len() < 2 {
+        return None;
+    }
+
+    let n = points.len() as f32;
+    let mean_t = (n - 1.) / 2.;
+    let mean_x = points.iter().map(|p| p[0]).sum::<f32>() / n;
+    let mean_y = points.iter().map(|p| p[1]).sum::<f32>() / n;
+
+    let (mut variance, mut cov_x, mut cov_y) = (0., 0., 0.);
+    for (t, [x, y]) in points.iter().enumerate() {
+        let dt = t as f32 - mean_t;
+        variance += dt * dt;
+        cov_x += dt * (x - mean_x);
+        cov_y += dt * (y - mean_y);
+    }
+    let (vx, vy) = (cov_x / variance, cov_y / variance);
+
+    let speed = vx.hypot(vy);
+    if speed < min_speed {
+        return None;
+    }
+
+    Some(Motion {
+        direction: if vy < 0. {
+            Direction::Up
+        } else {
+            Direction::Down
+        },
+        speed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cv::geometry::CountingGeometry;
+
+    fn run(sides: &[Side], min_frames: u32) -> Vec<Direction> {
+        let mut state = CrossingState::default();
+        sides
+            .iter()
+            .filter_map(|side| state.update(Some(*side), min_frames))
+            .collect()
+    }
+
+    #[test]
+    fn test_crossing_must_be_sustained() {
+        use Side::*;
+
+        // Flickers across the door for less than 3 frames
+        assert_eq!(
+            run(&[Outside, InZone, Inside, Inside, InZone, Inside], 3),
+            vec![]
+        );
+        assert_eq!(
+            run(&[Outside, InZone, Inside, Inside, Inside, Inside], 3),
+            vec![Direction::Up]
+        );
+    }
+
+    #[test]
+    fn test_turning_back_in_the_zone_cancels_the_crossing() {
+        use Side::*;
+
+        assert_eq!(
+            run(
+                &[Outside, InZone, Inside, InZone, Outside, Outside, Outside],
+                2
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_same_track_can_enter_then_leave() {
+        use Side::*;
+
+        assert_eq!(
+            run(
+                &[Outside, InZone, Inside, Inside, InZone, Outside, Outside],
+                2
+            ),
+            vec![Direction::Up, Direction::Down]
+        );
+    }
+
+    #[test]
+    fn test_going_around_the_door_is_not_a_crossing() {
+        let mut state = CrossingState::default();
+        for side in [Some(Side::Outside), None, Some(Side::Inside)] {
+            assert_eq!(state.update(side, 1), None);
+        }
+        assert_eq!(state.side(), Some(Side::Inside));
+    }
+
+    #[test]
+    fn test_walking_in_and_back_out_over_the_line() {
+        let line = CountingGeometry::default();
+        let mut state = CrossingState::default();
+
+        let crossings: Vec<Direction> = [0.9, 0.7, 0.51, 0.4, 0.3, 0.3, 0.49, 0.52, 0.6, 0.8]
+            .into_iter()
+            .filter_map(|y| state.update(line.side([0.5, y], 0.03), 2))
+            .collect();
+
+        assert_eq!(crossings, vec![Direction::Up, Direction::Down]);
+    }
+
+    #[test]
+    fn test_motion_ignores_jitter_and_loitering() {
+        // Walking up the frame, one position jumping back down
+        let walking = [[0.5, 0.8], [0.5, 0.7], [0.5, 0.72], [0.5, 0.5], [0.5, 0.4]];
+        let fitted = motion(&walking, 0.01).unwrap();
+        assert_eq!(fitted.direction, Direction::Up);
+        assert!((fitted.speed - 0.1).abs() < 1e-4);
+
+        // Swaying on the spot
+        let loitering = [
+            [0.5, 0.5],
+            [0.51, 0.52],
+            [0.49, 0.5],
+            [0.5, 0.49],
+            [0.5, 0.5],
+        ];
+        assert_eq!(motion(&loitering, 0.01), None);
+        assert_eq!(motion(&walking[..1], 0.01), None);
+    }
+}
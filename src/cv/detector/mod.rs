@@ -0,0 +1,253 @@
+use anyhow::{Context, Result};
+use log::{debug, error, info};
+use opencv::core::{CV_32F, Mat, Rect, Scalar, Size};
+use opencv::dnn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Instant;
+
+use crate::conf::ModelConf;
+use crate::cv::geometry::iou;
+
+mod ssd;
+mod yolo;
+
+pub use ssd::Ssd;
+pub use yolo::Yolo;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    pub rect: Rect,
+    pub confidence: f32,
+    pub class_id: usize,
+}
+
+/// Finds objects of the tracked classes in a frame
+pub trait Detector: Send {
+    /// Detections above the confidence threshold, in `frame` coordinates
+    fn detect(&mut self, frame: &Mat) -> Result<Vec<Detection>>;
+}
+
+impl fmt::Debug for dyn Detector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Detector")
+    }
+}
+
+/// How the output of the network is decoded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DetectorKind {
+    /// Single shot detector ending in a `DetectionOutput` layer (Caffe, TensorFlow)
+    #[default]
+    Ssd,
+    /// YOLO heads (Darknet, ONNX exports of YOLOv5 and YOLOv8)
+    Yolo,
+}
+
+impl FromStr for DetectorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ssd" => Ok(DetectorKind::Ssd),
+            "yolo" => Ok(DetectorKind::Yolo),
+            _ => Err(format!("Unknown detector {s:?}, expected ssd or yolo")),
+        }
+    }
+}
+
+impl fmt::Display for DetectorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DetectorKind::Ssd => write!(f, "ssd"),
+            DetectorKind::Yolo => write!(f, "yolo"),
+        }
+    }
+}
+
+/// Classes of the model output that are tracked, with their labels and
+/// confidence thresholds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Classes(HashMap<usize, (String, f32)>);
+
+impl Classes {
+    pub fn new(model: &ModelConf) -> Self {
+        Self(
+            model
+                .classes
+                .iter()
+                .map(|class| {
+                    let confidence = class.confidence.unwrap_or(model.confidence);
+                    (class.id, (class.label.clone(), confidence))
+                })
+                .collect(),
+        )
+    }
+
+    /// Whether a detection of `class_id` with `confidence` is kept
+    pub fn accepts(&self, class_id: usize, confidence: f32) -> bool {
+        self.0
+            .get(&class_id)
+            .is_some_and(|(_, threshold)| confidence > *threshold)
+    }
+
+    /// Label of `class_id`, empty for classes that are not tracked
+    pub fn label(&self, class_id: usize) -> &str {
+        self.0.get(&class_id).map_or("", |(label, _)| label)
+    }
+
+    /// Highest tracked class index
+    pub fn max_id(&self) -> Option<usize> {
+        self.0.keys().max().copied()
+    }
+}
+
+/// Turns a frame into the input blob of a network
+#[derive(Debug, Clone)]
+pub struct Normalization {
+    pub size: Size,
+    pub scale: f64,
+    pub mean: Scalar,
+    pub swap_rb: bool,
+}
+
+impl Normalization {
+    pub fn new(model: &ModelConf) -> Self {
+        let [b, g, r] = model.mean;
+        Self {
+            size: Size::new(model.input_width, model.input_height),
+            scale: model.scale,
+            mean: Scalar::new(b, g, r, 0.),
+            swap_rb: model.swap_rb,
+        }
+    }
+
+    /// `(pixel - mean) * scale`, resized to the input size
+    pub fn blob(&self, frame: &Mat) -> opencv::Result<Mat> {
+        dnn::blob_from_image(
+            frame,
+            self.scale,
+            self.size,
+            self.mean,
+            self.swap_rb,
+            false,
+            CV_32F,
+        )
+    }
+}
+
+/// Loads the network of `model` and the detector decoding its output
+pub fn open(model: &ModelConf) -> Result<Box<dyn Detector>> {
+    let net = load(model)?;
+
+    Ok(match model.kind {
+        DetectorKind::Ssd => Box::new(Ssd::new(net, model)),
+        DetectorKind::Yolo => Box::new(Yolo::new(net, model)?),
+    })
+}
+
+/// Reads a Caffe, TensorFlow, Darknet or ONNX network, the framework is
+/// picked from the file extensions
+pub fn load(model: &ModelConf) -> Result<dnn::Net> {
+    let weights = model.weights.to_string_lossy();
+    let config = model.proto.to_string_lossy();
+    debug!(
+        "Loading neural network model from files: config='{}', weights='{}'",
+        config, weights
+    );
+    let start_time = Instant::now();
+
+    match dnn::read_net(&weights, &config, "") {
+        Ok(net) => {
+            info!(
+                "Neural network loaded successfully in {:?}",
+                start_time.elapsed()
+            );
+            Ok(net)
+        }
+        Err(e) => {
+            error!("Failed to load neural network: {}", e);
+            Err(e).with_context(|| format!("Failed to load {}", model.weights.display()))
+        }
+    }
+}
+
+/// Greedy non-maximum suppression: of the boxes of a class overlapping by
+/// more than `threshold`, only the most confident one is kept
+pub fn nms(mut detections: Vec<Detection>, threshold: f32) -> Vec<Detection> {
+    detections.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+    let mut kept: Vec<Detection> = Vec::with_capacity(detections.len());
+    for detection in detections {
+        if kept
+            .iter()
+            .all(|k| k.class_id != detection.class_id || iou(k.rect, detection.rect) <= threshold)
+        {
+            kept.push(detection);
+        }
+    }
+
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conf::ClassConf;
+
+    fn detection(x: i32, confidence: f32, class_id: usize) -> Detection {
+        Detection {
+            rect: Rect::new(x, 0, 100, 100),
+            confidence,
+            class_id,
+        }
+    }
+
+    #[test]
+    fn test_nms_keeps_most_confident_of_each_class() {
+        let kept = nms(
+            vec![
+                detection(0, 0.6, 0),
+                detection(10, 0.9, 0),
+                detection(5, 0.7, 2),
+                detection(300, 0.5, 0),
+            ],
+            0.45,
+        );
+
+        assert_eq!(
+            kept,
+            vec![
+                detection(10, 0.9, 0),
+                detection(5, 0.7, 2),
+                detection(300, 0.5, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_classes_use_their_own_threshold() {
+        let classes = Classes::new(&ModelConf {
+            confidence: 0.5,
+            classes: vec![
+                ClassConf::person(0),
+                ClassConf {
+                    id: 1,
+                    label: "bicycle".into(),
+                    confidence: Some(0.3),
+                },
+            ],
+            ..ModelConf::default()
+        });
+
+        assert!(classes.accepts(0, 0.6));
+        assert!(!classes.accepts(0, 0.4));
+        assert!(classes.accepts(1, 0.4));
+        assert!(!classes.accepts(2, 0.9));
+        assert_eq!(classes.label(1), "bicycle");
+        assert_eq!(classes.max_id(), Some(1));
+    }
+}
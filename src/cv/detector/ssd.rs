@@ -0,0 +1,84 @@
+use anyhow::{Result, bail};
+use log::debug;
+use opencv::core::{Mat, MatTraitConst, Rect};
+use opencv::dnn::{self, NetTrait};
+use rayon::prelude::*;
+
+use super::{Classes, Detection, Detector, Normalization};
+use crate::conf::ModelConf;
+use crate::cv::mat_view::MatViewND;
+
+/// Single shot detector, its `DetectionOutput` layer gives one
+/// `[image, class, confidence, x1, y1, x2, y2]` row per detection with
+/// normalized coordinates
+pub struct Ssd {
+    net: dnn::Net,
+    normalization: Normalization,
+    classes: Classes,
+}
+
+impl Ssd {
+    pub fn new(net: dnn::Net, model: &ModelConf) -> Self {
+        Self {
+            net,
+            normalization: Normalization::new(model),
+            classes: Classes::new(model),
+        }
+    }
+}
+
+impl Detector for Ssd {
+    fn detect(&mut self, frame: &Mat) -> Result<Vec<Detection>> {
+        let blob = self.normalization.blob(frame)?;
+        self.net.set_input_def(&blob)?;
+
+        let output = self.net.forward_single_def()?;
+        debug!("Ran net fwd");
+
+        let sizes = output.mat_size();
+        if sizes.len() != 4 {
+            bail!(
+                "Unexpected output size. Expected: 4 Received: {}",
+                sizes.len()
+            );
+        }
+
+        let num_detections = sizes[2] as usize;
+        let mut clone = output.clone();
+        let mv = MatViewND::<f32>::new(&mut clone)?;
+        let (w, h) = (frame.cols() as f32, frame.rows() as f32);
+
+        // Parallel processing of detections
+        let detections = (0..num_detections)
+            .into_par_iter()
+            .filter_map(|i| {
+                let i = i as i32;
+                let confidence = *mv.get(&[0, 0, i, 2]).ok()?;
+                let class_id = *mv.get(&[0, 0, i, 1]).ok()? as usize;
+                if !self.classes.accepts(class_id, confidence) {
+                    return None;
+                }
+
+                let start_x = (mv.get(&[0, 0, i, 3]).ok()? * w) as i32;
+                let start_y = (mv.get(&[0, 0, i, 4]).ok()? * h) as i32;
+                let end_x = (mv.get(&[0, 0, i, 5]).ok()? * w) as i32;
+                let end_y = (mv.get(&[0, 0, i, 6]).ok()? * h) as i32;
+
+                let rect = Rect::new(
+                    start_x.max(0),
+                    start_y.max(0),
+                    (end_x - start_x).max(1),
+                    (end_y - start_y).max(1),
+                );
+
+                Some(Detection {
+                    rect,
+                    confidence,
+                    class_id,
+                })
+            })
+            .collect();
+
+        Ok(detections)
+    }
+}
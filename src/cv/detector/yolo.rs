@@ -0,0 +1,182 @@
+use anyhow::{Result, bail};
+use log::debug;
+use opencv::core::{Mat, MatTraitConst, MatTraitConstManual, Rect, Size, Vector};
+use opencv::dnn::{self, NetTrait, NetTraitConst};
+
+use super::{Classes, Detection, Detector, Normalization};
+use crate::conf::ModelConf;
+
+/// YOLO detector, the output layout is recognized from its shape:
+///
+/// - `[anchors, 5 + classes]`, Darknet through OpenCV, normalized coordinates
+/// - `[1, anchors, 5 + classes]`, YOLOv5 ONNX exports, input pixels
+/// - `[1, 4 + classes, anchors]`, YOLOv8 ONNX exports, input pixels and no
+///   objectness
+///
+/// Boxes are `[cx, cy, w, h]`, overlapping ones are left to the NMS of the
+/// detection filter.
+pub struct Yolo {
+    net: dnn::Net,
+    out_names: Vector<String>,
+    normalization: Normalization,
+    classes: Classes,
+}
+
+impl Yolo {
+    pub fn new(net: dnn::Net, model: &ModelConf) -> Result<Self> {
+        Ok(Self {
+            out_names: net.get_unconnected_out_layers_names()?,
+            net,
+            normalization: Normalization::new(model),
+            classes: Classes::new(model),
+        })
+    }
+}
+
+impl Detector for Yolo {
+    fn detect(&mut self, frame: &Mat) -> Result<Vec<Detection>> {
+        let blob = self.normalization.blob(frame)?;
+        self.net.set_input_def(&blob)?;
+
+        let mut outputs = Vector::<Mat>::new();
+        self.net.forward(&mut outputs, &self.out_names)?;
+        debug!("Ran net fwd");
+
+        let mut detections = Vec::new();
+        for output in &outputs {
+            detections.extend(decode(
+                output.data_typed::<f32>()?,
+                &output.mat_size(),
+                frame.size()?,
+                self.normalization.size,
+                &self.classes,
+            )?);
+        }
+
+        Ok(detections)
+    }
+}
+
+/// Detections of the tracked classes in one output tensor, scaled to `frame`
+fn decode(
+    data: &[f32],
+    dims: &[i32],
+    frame: Size,
+    input: Size,
+    classes: &Classes,
+) -> Result<Vec<Detection>> {
+    let (rows, cols, transposed, normalized, objectness) = match *dims {
+        [rows, cols] => (rows, cols, false, true, true),
+        [1, rows, cols] if rows > cols => (rows, cols, false, false, true),
+        [1, cols, rows] => (rows, cols, true, false, false),
+        _ => bail!("Unexpected YOLO output shape {:?}", dims),
+    };
+    let (rows, cols) = (rows as usize, cols as usize);
+    if data.len() < rows * cols {
+        bail!(
+            "YOLO output holds {} values, expected {}",
+            data.len(),
+            rows * cols
+        );
+    }
+
+    let first_class = if objectness { 5 } else { 4 };
+    if let Some(max_id) = classes.max_id()
+        && cols <= first_class + max_id
+    {
+        bail!(
+            "YOLO output has {} classes, no class {}",
+            cols.saturating_sub(first_class),
+            max_id
+        );
+    }
+
+    let at = |row: usize, col: usize| {
+        if transposed {
+            data[col * rows + row]
+        } else {
+            data[row * cols + col]
+        }
+    };
+    let (sx, sy) = if normalized {
+        (frame.width as f32, frame.height as f32)
+    } else {
+        (
+            frame.width as f32 / input.width as f32,
+            frame.height as f32 / input.height as f32,
+        )
+    };
+
+    let mut detections = Vec::new();
+    for row in 0..rows {
+        let (class_id, score) = (first_class..cols)
+            .map(|col| (col - first_class, at(row, col)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or_default();
+        let score = if objectness {
+            score * at(row, 4)
+        } else {
+            score
+        };
+        if !classes.accepts(class_id, score) {
+            continue;
+        }
+
+        let (cx, cy) = (at(row, 0) * sx, at(row, 1) * sy);
+        let (w, h) = (at(row, 2) * sx, at(row, 3) * sy);
+        let (x, y) = ((cx - w / 2.).max(0.), (cy - h / 2.).max(0.));
+
+        detections.push(Detection {
+            rect: Rect::new(x as i32, y as i32, (w as i32).max(1), (h as i32).max(1)),
+            confidence: score,
+            class_id,
+        });
+    }
+
+    Ok(detections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conf::ClassConf;
+
+    #[test]
+    fn test_decodes_transposed_output() {
+        // [1, 4 + 2 classes, 8 anchors], only the second anchor is a person
+        let mut data = vec![0.; 6 * 8];
+        let mut set =
+            |channel: usize, anchor: usize, value: f32| data[channel * 8 + anchor] = value;
+        for (channel, value) in [100., 100., 20., 40., 0.9, 0.1].into_iter().enumerate() {
+            set(channel, 0, value);
+        }
+        for (channel, value) in [200., 200., 40., 80., 0.1, 0.8].into_iter().enumerate() {
+            set(channel, 1, value);
+        }
+        for (channel, value) in [300., 300., 60., 120., 0.1, 0.2].into_iter().enumerate() {
+            set(channel, 2, value);
+        }
+
+        let detections = decode(
+            &data,
+            &[1, 6, 8],
+            Size::new(320, 320),
+            Size::new(640, 640),
+            &Classes::new(&ModelConf {
+                confidence: 0.5,
+                classes: vec![ClassConf::person(1)],
+                ..ModelConf::default()
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(
+            detections,
+            vec![Detection {
+                rect: Rect::new(90, 80, 20, 40),
+                confidence: 0.8,
+                class_id: 1,
+            }]
+        );
+    }
+}
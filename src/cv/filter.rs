@@ -0,0 +1,106 @@
+use log::debug;
+use opencv::core::Size;
+
+use crate::conf::FilterConf;
+use crate::cv::centroid::Centroid;
+use crate::cv::detector::{Detection, nms};
+use crate::cv::geometry::{normalize, point_in_polygon};
+use crate::cv::preprocess::Transform;
+
+/// Drops detections that should not become tracks, before trackers are
+/// created for them
+#[derive(Debug, Clone)]
+pub struct DetectionFilter {
+    conf: FilterConf,
+}
+
+impl DetectionFilter {
+    pub fn new(conf: &FilterConf) -> Self {
+        Self { conf: conf.clone() }
+    }
+
+    /// Applies NMS, then the size, shape and region limits to detections
+    /// made on the network input of `transform`.
+    ///
+    /// The limits apply to the boxes mapped back into the camera frame.
+    pub fn apply(&self, detections: Vec<Detection>, transform: &Transform) -> Vec<Detection> {
+        let count = detections.len();
+        let size = transform.frame_size;
+        let frame_area = (size.width * size.height) as f32;
+
+        let kept: Vec<Detection> = nms(detections, self.conf.nms_threshold)
+            .into_iter()
+            .filter(|detection| {
+                let rect = transform.to_frame(detection.rect);
+                let area = (rect.width * rect.height) as f32 / frame_area;
+                let aspect = rect.width as f32 / rect.height as f32;
+
+                (self.conf.min_area..=self.conf.max_area).contains(&area)
+                    && (self.conf.min_aspect..=self.conf.max_aspect).contains(&aspect)
+                    && self.in_roi(&Centroid::from_rect(rect), size)
+            })
+            .collect();
+
+        if kept.len() < count {
+            debug!(
+                "Filtered out {} of {} detections",
+                count - kept.len(),
+                count
+            );
+        }
+        kept
+    }
+
+    fn in_roi(&self, centroid: &Centroid, size: Size) -> bool {
+        self.conf.roi.is_empty()
+            || point_in_polygon(normalize(centroid, size.width, size.height), &self.conf.roi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conf::PreprocessConf;
+    use opencv::core::Rect;
+
+    fn detection(rect: Rect, confidence: f32) -> Detection {
+        Detection {
+            rect,
+            confidence,
+            class_id: 15,
+        }
+    }
+
+    #[test]
+    fn test_filters_overlaps_size_shape_and_region() {
+        let filter = DetectionFilter::new(&FilterConf {
+            min_area: 0.01,
+            max_area: 0.5,
+            max_aspect: 1.5,
+            roi: vec![[0.0, 0.0], [0.5, 0.0], [0.5, 1.0], [0.0, 1.0]],
+            ..FilterConf::default()
+        });
+
+        let person = detection(Rect::new(20, 20, 40, 80), 0.9);
+        let kept = filter.apply(
+            vec![
+                person,
+                // Same person, less confident
+                detection(Rect::new(24, 22, 40, 80), 0.6),
+                // Too small
+                detection(Rect::new(100, 20, 4, 8), 0.9),
+                // Too wide
+                detection(Rect::new(60, 150, 90, 20), 0.9),
+                // Outside of the region
+                detection(Rect::new(250, 20, 40, 80), 0.9),
+            ],
+            &Transform::new(
+                Size::new(300, 300),
+                &PreprocessConf::default(),
+                Size::new(300, 300),
+            ),
+        );
+
+        assert_eq!(kept, vec![person]);
+    }
+}
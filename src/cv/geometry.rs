@@ -0,0 +1,185 @@
+use anyhow::{Result, bail};
+use opencv::core::{Mat, MatTraitConst, Point, Rect, Scalar, Vector};
+use opencv::imgproc;
+use serde::{Deserialize, Serialize};
+
+use crate::cv::centroid::Centroid;
+use crate::cv::crossing::Side;
+
+/// A point in normalized frame coordinates, `[0, 0]` is the top left corner
+/// and `[1, 1]` the bottom right one
+pub type NormPoint = [f32; 2];
+
+/// Geometry used to decide when a tracked object goes through the door
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CountingGeometry {
+    /// Objects going through the segment from its right hand side to its left
+    /// hand side (as seen walking from `start` to `end`) enter, the opposite
+    /// way they exit
+    Line { start: NormPoint, end: NormPoint },
+    /// Objects reaching the `entry` polygon after being in the `exit` polygon
+    /// enter, the opposite way they exit
+    Zones {
+        entry: Vec<NormPoint>,
+        exit: Vec<NormPoint>,
+    },
+}
+
+impl Default for CountingGeometry {
+    fn default() -> Self {
+        CountingGeometry::Line {
+            start: [0.0, 0.5],
+            end: [1.0, 0.5],
+        }
+    }
+}
+
+impl CountingGeometry {
+    pub fn validate(&self) -> Result<()> {
+        let in_frame = |p: &NormPoint| (0.0..=1.0).contains(&p[0]) && (0.0..=1.0).contains(&p[1]);
+
+        match self {
+            CountingGeometry::Line { start, end } => {
+                if start == end {
+                    bail!("Counting line start and end must be different points");
+                }
+                if !in_frame(start) || !in_frame(end) {
+                    bail!("Counting line points must be normalized to [0, 1]");
+                }
+            }
+            CountingGeometry::Zones { entry, exit } => {
+                if entry.len() < 3 || exit.len() < 3 {
+                    bail!("Counting zones need at least 3 points each");
+                }
+                if !entry.iter().chain(exit).all(in_frame) {
+                    bail!("Counting zone points must be normalized to [0, 1]");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Side of the door `point` stands on, `None` for a point off a counting
+    /// line's ends.
+    ///
+    /// Points within `margin` of the line are in its zone, for zones the gap
+    /// between the two polygons is. Points are normalized.
+    pub fn side(&self, point: NormPoint, margin: f32) -> Option<Side> {
+        match self {
+            CountingGeometry::Line { start, end } => {
+                let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+                let length = dx.hypot(dy);
+                let along = ((point[0] - start[0]) * dx + (point[1] - start[1]) * dy) / length;
+                if !(0.0..=length).contains(&along) {
+                    return None;
+                }
+
+                // Right hand side is outside, see `CountingGeometry::Line`
+                let distance = cross(*start, *end, point) / length;
+                Some(if distance.abs() <= margin {
+                    Side::InZone
+                } else if distance > 0.0 {
+                    Side::Outside
+                } else {
+                    Side::Inside
+                })
+            }
+            CountingGeometry::Zones { entry, exit } => Some(if point_in_polygon(point, entry) {
+                Side::Inside
+            } else if point_in_polygon(point, exit) {
+                Side::Outside
+            } else {
+                Side::InZone
+            }),
+        }
+    }
+
+    /// Draws the counting geometry over `frame`
+    pub fn draw(&self, frame: &mut Mat) -> opencv::Result<()> {
+        let (w, h) = (frame.cols(), frame.rows());
+
+        match self {
+            CountingGeometry::Line { start, end } => imgproc::line(
+                frame,
+                to_pixels(*start, w, h),
+                to_pixels(*end, w, h),
+                Scalar::new(0., 255., 0., 0.),
+                2,
+                imgproc::LINE_8,
+                0,
+            ),
+            CountingGeometry::Zones { entry, exit } => {
+                for (zone, color) in [
+                    (entry, Scalar::new(0., 255., 0., 0.)),
+                    (exit, Scalar::new(0., 0., 255., 0.)),
+                ] {
+                    let points: Vector<Point> = zone.iter().map(|p| to_pixels(*p, w, h)).collect();
+                    let contours: Vector<Vector<Point>> = Vector::from_iter([points]);
+                    imgproc::polylines(frame, &contours, true, color, 2, imgproc::LINE_8, 0)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Normalizes a centroid in a frame of `width`x`height` pixels
+pub fn normalize(centroid: &Centroid, width: i32, height: i32) -> NormPoint {
+    [
+        centroid.x as f32 / width as f32,
+        centroid.y as f32 / height as f32,
+    ]
+}
+
+pub fn to_pixels(point: NormPoint, width: i32, height: i32) -> Point {
+    Point::new(
+        (point[0] * width as f32).round() as i32,
+        (point[1] * height as f32).round() as i32,
+    )
+}
+
+/// Intersection over union of two boxes, 0 when they do not overlap
+pub fn iou(a: Rect, b: Rect) -> f32 {
+    let width = (a.x + a.width).min(b.x + b.width) - a.x.max(b.x);
+    let height = (a.y + a.height).min(b.y + b.height) - a.y.max(b.y);
+    if width <= 0 || height <= 0 {
+        return 0.0;
+    }
+
+    let intersection = (width * height) as f32;
+    let union = (a.width * a.height + b.width * b.height) as f32 - intersection;
+    if union <= 0.0 {
+        return 0.0;
+    }
+
+    intersection / union
+}
+
+/// Ray casting point in polygon test
+pub fn point_in_polygon(point: NormPoint, polygon: &[NormPoint]) -> bool {
+    if polygon.len() < 3 {
+        return false;
+    }
+
+    let [x, y] = point;
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+
+    for (i, &[xi, yi]) in polygon.iter().enumerate() {
+        let [xj, yj] = polygon[j];
+
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+
+    inside
+}
+
+/// Z component of the cross product of `a -> b` and `a -> p`
+fn cross(a: NormPoint, b: NormPoint, p: NormPoint) -> f32 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
@@ -1,12 +1,21 @@
 pub mod centroid;
+pub mod crossing;
+pub mod detector;
+pub mod filter;
 pub mod frame_metrics;
+pub mod geometry;
 pub mod mat_view;
 pub mod net;
+pub mod preprocess;
+pub mod reid;
+pub mod tracker;
 
 use log::{debug, info, warning};
+use opencv::core::Rect;
 use opencv::highgui;
 use opencv::videoio::{CAP_ANY, VideoCapture};
-use tokio::time::Instant;
+use std::collections::BTreeMap;
+use std::time::Instant;
 
 use crate::direction::Direction;
 
@@ -38,20 +47,83 @@ pub fn init_window() -> &'static str {
     WINNAME
 }
 
+#[derive(Debug, Clone)]
 pub struct CvDetection {
+    oid: u32,
+    label: String,
     instant: Instant,
     direction: Direction,
+    bbox: Rect,
 }
 
 impl CvDetection {
-    pub fn new(direction: Direction) -> Self {
+    pub fn new(oid: u32, label: String, direction: Direction, bbox: Rect) -> Self {
         Self {
+            oid,
+            label,
             instant: Instant::now(),
             direction,
+            bbox,
         }
     }
 
-    pub fn new_with_time(direction: Direction, instant: Instant) -> Self {
-        Self { instant, direction }
+    pub fn new_with_time(
+        oid: u32,
+        label: String,
+        direction: Direction,
+        bbox: Rect,
+        instant: Instant,
+    ) -> Self {
+        Self {
+            oid,
+            label,
+            instant,
+            direction,
+            bbox,
+        }
+    }
+
+    pub fn oid(&self) -> u32 {
+        self.oid
+    }
+
+    /// Label of the class of the object that crossed
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn instant(&self) -> Instant {
+        self.instant
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Bounding box of the object at the moment it crossed, in full frame coordinates
+    pub fn bbox(&self) -> Rect {
+        self.bbox
+    }
+}
+
+/// Entries and exits of each class, by label
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tallies(BTreeMap<String, (usize, usize)>);
+
+impl Tallies {
+    pub fn add(&mut self, crossing: &CvDetection) {
+        let tally = self.0.entry(crossing.label().to_owned()).or_default();
+        match crossing.direction() {
+            Direction::Up => tally.0 += 1,
+            Direction::Down => tally.1 += 1,
+            Direction::Stationary => {}
+        }
+    }
+
+    /// Label, entries and exits of every class that crossed, by label
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize, usize)> {
+        self.0
+            .iter()
+            .map(|(label, (entered, exited))| (label.as_str(), *entered, *exited))
     }
 }
@@ -1,235 +1,326 @@
+use crate::conf::{CrossingConf, FilterConf, ModelConf, PreprocessConf, ReidConf, TrackerConf};
 use crate::cv::centroid::CentroidTracker;
-use crate::cv::mat_view::MatViewND;
+use crate::cv::crossing;
+use crate::cv::detector::{self, Classes, Detector};
+use crate::cv::filter::DetectionFilter;
+use crate::cv::geometry::{CountingGeometry, NormPoint};
+use crate::cv::preprocess::Transform;
+use crate::cv::reid::{Describer, Gallery};
+use crate::cv::tracker::{self, ObjectTracker, TrackerBackend};
+use crate::cv::{CvDetection, Tallies};
 use crate::direction::Direction;
-use anyhow::{Result, bail};
+use anyhow::{Context, Result};
 use clap::FromArgMatches;
 use log::{debug, error, info, warning};
-use opencv::Error;
 use opencv::core::*;
-use opencv::dnn;
-use opencv::dnn::NetTrait;
 use opencv::imgproc;
-use opencv::tracking::*;
-use opencv::video::TrackerTrait;
 use rayon::prelude::*;
 use std::ops::Deref;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::*;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
 
-#[derive(Debug, Clone)]
+/// A tracker and the number of detection frames in a row it went without
+/// a matching detection
+#[derive(Debug)]
+struct LiveTracker {
+    tracker: Arc<Mutex<Box<dyn ObjectTracker>>>,
+    misses: u32,
+}
+
+#[derive(Debug)]
 pub struct Net {
-    net: dnn::Net,
-    trackers: Vec<Arc<Mutex<Ptr<TrackerKCF>>>>,
-    confidence: f32,
+    detector: Box<dyn Detector>,
+    classes: Classes,
+    filter: DetectionFilter,
+    backend: TrackerBackend,
+    /// Live trackers, `tracked_rects` and `tracked_labels` hold where each
+    /// one is and what it follows
+    trackers: Vec<LiveTracker>,
     tracked_rects: Vec<Rect>,
+    tracked_labels: Vec<String>,
+    match_iou: f32,
+    reinit_iou: f32,
+    max_misses: u32,
     skip_frames: u32,
     input_size: Size,
+    preprocess: PreprocessConf,
+    /// Made for the size of the last frame
+    transform: Transform,
     frame_count: u32,
     centroid_tracker: CentroidTracker,
+    describer: Option<Describer>,
+    counting: CountingGeometry,
+    crossing: CrossingConf,
+    tallies: Tallies,
+    detection_tx: Option<mpsc::Sender<CvDetection>>,
 }
 
 impl Net {
     pub fn new(
-        prototxt: &str,
-        caffe_model: &str,
-        confidence: f32,
-        skip_frames: u32,
-        input_size: Size,
-    ) -> opencv::Result<Self> {
-        debug!(
-            "Loading neural network model from files: proto='{}', model='{}'",
-            prototxt, caffe_model
-        );
-        let start_time = Instant::now();
-
-        let net = match dnn::read_net_from_caffe(prototxt, caffe_model) {
-            Ok(net) => {
-                info!(
-                    "Neural network loaded successfully in {:?}",
-                    start_time.elapsed()
-                );
-                net
-            }
-            Err(e) => {
-                error!("Failed to load neural network: {}", e);
-                return Err(e);
-            }
-        };
+        model: &ModelConf,
+        preprocess: &PreprocessConf,
+        filter: &FilterConf,
+        tracker: &TrackerConf,
+        reid: &ReidConf,
+        counting: CountingGeometry,
+        crossing: &CrossingConf,
+    ) -> Result<Self> {
+        let detector = detector::open(model)?;
+        let input_size = Size::new(model.input_width, model.input_height);
+        info!("Detecting people with {} model", model.kind);
+
+        info!("Tracking objects with {}", tracker.backend);
+        let describer = Describer::new(reid)?;
+        let mut centroid_tracker = CentroidTracker::new(tracker);
+        if describer.is_some() {
+            info!("Re-identifying lost tracks by {}", reid.mode);
+            centroid_tracker.set_gallery(Gallery::new(reid));
+        }
 
         Ok(Self {
-            net,
+            detector,
+            classes: Classes::new(model),
+            filter: DetectionFilter::new(filter),
+            backend: tracker.backend.clone(),
             trackers: Vec::new(),
-            confidence,
             tracked_rects: Vec::new(),
-            skip_frames,
+            tracked_labels: Vec::new(),
+            match_iou: tracker.match_iou,
+            reinit_iou: tracker.reinit_iou,
+            max_misses: tracker.max_misses,
+            // Without a tracker the model has to see every frame
+            skip_frames: match tracker.backend {
+                TrackerBackend::None => 1,
+                _ => model.skip_frames.max(1),
+            },
             input_size,
+            preprocess: preprocess.clone(),
+            transform: Transform::new(input_size, preprocess, input_size),
             frame_count: 0,
-            centroid_tracker: CentroidTracker::new(3, 20.),
+            centroid_tracker,
+            describer,
+            counting,
+            crossing: crossing.clone(),
+            tallies: Tallies::default(),
+            detection_tx: None,
         })
     }
 
-    const CLASSES: [&str; 21] = [
-        "background",
-        "aeroplane",
-        "bicycle",
-        "bird",
-        "boat",
-        "bottle",
-        "bus",
-        "car",
-        "cat",
-        "chair",
-        "cow",
-        "diningtable",
-        "dog",
-        "horse",
-        "motorbike",
-        "person",
-        "pottedplant",
-        "sheep",
-        "sofa",
-        "train",
-        "tvmonitor",
-    ];
-
-    pub fn preprocess_frame(&self, frame: &Mat) -> opencv::Result<Mat> {
-        #[cfg(debug_assertions)]
-        debug!("Preprocessing frame: starting transformation pipeline");
-
-        #[cfg(debug_assertions)]
-        let start_time = Instant::now();
-
-        let mut rotated = Mat::default();
-        #[cfg(debug_assertions)]
-        debug!("Rotating frame by 180 degrees");
-
-        if let Err(e) = rotate(&frame, &mut rotated, ROTATE_180) {
-            error!("Failed to rotate frame: {}", e);
-            return Err(e);
-        }
-
-        let mut resized = Mat::default();
-        #[cfg(debug_assertions)]
-        debug!("Resizing frame to 500x500");
-
-        match imgproc::resize(
-            &frame,
-            // &rotated, // Not rotating for testing
-            &mut resized,
-            self.input_size,
-            0.,
-            0.,
-            imgproc::INTER_AREA,
-        ) {
-            Ok(_) => {}
-            Err(e) => {
-                error!("Failed to resize frame: {}", e);
-                return Err(e);
-            }
-        }
+    /// Publishes a [`CvDetection`] on `tx` every time a tracked object crosses the counting line
+    pub fn set_detection_sender(&mut self, tx: mpsc::Sender<CvDetection>) {
+        self.detection_tx = Some(tx);
+    }
 
-        #[cfg(debug_assertions)]
-        debug!(
-            "Frame preprocessing completed in {:?}",
-            start_time.elapsed()
-        );
-        Ok(resized)
+    /// Runs detection or tracking on `full_frame` and counts crossings.
+    ///
+    /// Nothing is drawn, use [`Net::draw_tracking_results`] to render the
+    /// overlay when it is needed.
+    pub fn process_frame(&mut self, full_frame: &Mat) -> Result<()> {
+        self.process_frame_at(full_frame, Instant::now())
     }
 
-    pub fn process_frame(&mut self, full_frame: &Mat) -> Result<Mat> {
-        // 1. Run detection/tracking on a downscaled copy
-        let small_size = self.input_size;
-        let mut small = Mat::default();
-        imgproc::resize(
-            full_frame,
-            &mut small,
-            small_size,
-            0.,
-            0.,
-            imgproc::INTER_AREA,
-        )?;
+    /// Same as [`Net::process_frame`] for a frame captured at `now`, crossings
+    /// are stamped with it instead of the current time
+    pub fn process_frame_at(&mut self, full_frame: &Mat, now: Instant) -> Result<()> {
+        let full_size = full_frame.size()?;
+        if self.transform.frame_size != full_size {
+            self.transform = Transform::new(full_size, &self.preprocess, self.input_size);
+            debug!("Preprocessing {:?} frames: {:?}", full_size, self.transform);
+        }
+
+        // 1. Run detection/tracking on the network input, made once per frame
+        let small = self.transform.apply(full_frame)?;
 
         // 2. Detection or tracking on `small`
         if self.frame_count % self.skip_frames == 0 {
-            self.trackers.clear();
-            self.tracked_rects.clear();
-            let detections = self.detect_objects(&small)?;
-            for (rect, conf) in detections {
-                if conf > self.confidence {
-                    self.create_tracker(&small, rect)?;
-                }
-            }
+            let detections = self.detector.detect(&small)?;
+            let detections = self.filter.apply(detections, &self.transform);
+            self.reconcile(&small, &detections)?;
         } else {
             self.update_trackers(&small)?;
         }
 
-        let mid_y = 250; // TODO: Set the zones through config
-
         let rects = self.tracked_rects.clone();
-        let objects = self.centroid_tracker.update(&rects)?;
+        let mut descriptors = Vec::new();
+        if let Some(describer) = &mut self.describer {
+            for rect in &rects {
+                descriptors.push(describer.describe(&small, *rect)?);
+            }
+        }
+        let objects = self
+            .centroid_tracker
+            .update(&rects, &self.tracked_labels, &descriptors)?;
+        let mut crossings = Vec::new();
 
         for (object_id, centroid) in &objects {
             if let Some(obj) = self.centroid_tracker.objects.get_mut(object_id) {
-                if obj.centroids.len() >= 2 {
-                    let prev_y = obj.centroids[obj.centroids.len() - 2].y;
-                    let current_y = centroid.y;
-
-                    let direction = if current_y < prev_y {
-                        Direction::Up
-                    } else {
-                        Direction::Down
-                    };
-
-                    if !obj.counted {
-                        if direction == Direction::Up && current_y < mid_y {
-                            obj.counted = true;
-                            info!("Obj: {} entered", obj.oid);
-                        } else if direction == Direction::Down && current_y > mid_y {
-                            obj.counted = true;
-                            info!("Obj: {} exited", obj.oid);
-                        }
+                let recent = obj.centroids.len().saturating_sub(self.crossing.window);
+                let points: Vec<NormPoint> = obj.centroids[recent..]
+                    .iter()
+                    .map(|centroid| self.transform.normalize(centroid))
+                    .collect();
+                let motion = crossing::motion(&points, self.crossing.min_speed);
+                obj.direction = motion.map_or(Direction::Stationary, |m| m.direction);
+                obj.speed = motion.map_or(0., |m| m.speed);
+
+                // Loitering tracks keep their crossing state until they move again
+                if obj.direction != Direction::Stationary {
+                    let side = self
+                        .counting
+                        .side(self.transform.normalize(centroid), self.crossing.margin);
+                    if let Some(crossed) = obj.crossing.update(side, self.crossing.min_frames) {
+                        info!("Obj: {} ({}) {}", obj.oid, obj.label, crossed.as_action());
+                        let rect = obj.rects.last().copied().unwrap_or_default();
+                        crossings.push((obj.oid, obj.label.clone(), crossed, rect));
                     }
-
-                    obj.last_direction = Some(direction);
                 }
 
                 if obj.centroids.len() > 50 {
                     obj.centroids.remove(0);
+                    obj.rects.remove(0);
                 }
             };
         }
 
-        // 3. Prepare output image (clone full resolution)
-        let mut out = full_frame.clone();
+        for (oid, label, direction, rect) in crossings {
+            let bbox = self.transform.to_frame(rect);
+            let crossing = CvDetection::new_with_time(oid, label, direction, bbox, now);
+            self.tallies.add(&crossing);
+            self.publish(crossing);
+        }
 
-        // 4. Draw scaled tracking results
-        self.draw_tracking_results(&mut out)?;
         self.frame_count += 1;
 
-        Ok(out)
+        Ok(())
+    }
+
+    /// Entries and exits counted so far, by class
+    pub fn tallies(&self) -> &Tallies {
+        &self.tallies
+    }
+
+    fn publish(&self, detection: CvDetection) {
+        let Some(tx) = &self.detection_tx else {
+            return;
+        };
+
+        match tx.try_send(detection) {
+            Ok(_) => {}
+            Err(TrySendError::Full(detection)) => {
+                warning!(
+                    "Detection channel full, dropping crossing of obj {}",
+                    detection.oid()
+                );
+            }
+            Err(TrySendError::Closed(_)) => {
+                debug!("Detection channel closed");
+            }
+        }
     }
 
-    fn create_tracker(&mut self, frame: &Mat, rect: Rect) -> Result<()> {
-        let mut tracker = TrackerKCF::create(TrackerKCF_Params::default()?)?;
+    /// Merges the detections of a detection frame with the live trackers.
+    ///
+    /// Trackers that still follow a detection are kept, drifted ones are
+    /// restarted on it, and those without a detection for more than
+    /// `max_misses` detection frames are retired.
+    fn reconcile(&mut self, frame: &Mat, detections: &[detector::Detection]) -> Result<()> {
+        let rects: Vec<Rect> = detections.iter().map(|d| d.rect).collect();
+        let labels: Vec<String> = detections
+            .iter()
+            .map(|d| self.classes.label(d.class_id).to_owned())
+            .collect();
+        if self.backend.is_none() {
+            self.tracked_rects = rects;
+            self.tracked_labels = labels;
+            return Ok(());
+        }
+
+        let plan = tracker::reconcile(
+            &self.tracked_rects,
+            &self.tracked_labels,
+            &rects,
+            &labels,
+            self.match_iou,
+            self.reinit_iou,
+        );
+        let mut live: Vec<Option<LiveTracker>> = std::mem::take(&mut self.trackers)
+            .into_iter()
+            .map(Some)
+            .collect();
+        let mut trackers = Vec::with_capacity(plan.matched.len() + plan.new.len());
+        let mut tracked_rects = Vec::with_capacity(trackers.capacity());
+        let mut tracked_labels = Vec::with_capacity(trackers.capacity());
+
+        for (t, d, drifted) in plan.matched {
+            let Some(mut tracker) = live[t].take() else {
+                continue;
+            };
+            if drifted {
+                debug!("Restarting drifted tracker on {:?}", rects[d]);
+                tracker = self.start_tracker(frame, rects[d])?;
+            }
+            tracker.misses = 0;
+            trackers.push(tracker);
+            tracked_rects.push(rects[d]);
+            tracked_labels.push(labels[d].clone());
+        }
+
+        for t in plan.missed {
+            let Some(mut tracker) = live[t].take() else {
+                continue;
+            };
+            tracker.misses += 1;
+            if tracker.misses > self.max_misses {
+                debug!("Retiring tracker at {:?}", self.tracked_rects[t]);
+                continue;
+            }
+            trackers.push(tracker);
+            tracked_rects.push(self.tracked_rects[t]);
+            tracked_labels.push(self.tracked_labels[t].clone());
+        }
+
+        for d in plan.new {
+            trackers.push(self.start_tracker(frame, rects[d])?);
+            tracked_rects.push(rects[d]);
+            tracked_labels.push(labels[d].clone());
+        }
+
+        self.trackers = trackers;
+        self.tracked_rects = tracked_rects;
+        self.tracked_labels = tracked_labels;
+        Ok(())
+    }
 
+    /// A tracker following the object in `rect`
+    fn start_tracker(&self, frame: &Mat, rect: Rect) -> Result<LiveTracker> {
+        let mut tracker = self
+            .backend
+            .create()?
+            .context("The tracker backend has no tracker")?;
         tracker
             .init(frame, rect)
-            .map_err(|e| Error::new(1, format!("Tracker init failed: {}", e)))?;
+            .map_err(|e| anyhow::anyhow!("Tracker init failed: {}", e))?;
 
-        self.trackers.push(Arc::new(Mutex::new(tracker)));
-        Ok(())
+        Ok(LiveTracker {
+            tracker: Arc::new(Mutex::new(tracker)),
+            misses: 0,
+        })
     }
 
     fn update_trackers(&mut self, frame: &Mat) -> Result<()> {
         let temp_trackers = std::mem::take(&mut self.trackers);
+        let labels = std::mem::take(&mut self.tracked_labels);
 
         // Parallel processing of tracker updates
-        let results: Vec<(bool, Rect, Arc<Mutex<Ptr<TrackerKCF>>>)> = temp_trackers
+        let results: Vec<(bool, Rect, LiveTracker, String)> = temp_trackers
             .into_par_iter()
-            .map(|tracker| {
+            .zip(labels)
+            .map(|(tracker, label)| {
                 let (success, bbox) = {
-                    let mut locked = match tracker.lock() {
+                    let mut locked = match tracker.tracker.lock() {
                         Ok(guard) => guard,
                         Err(poisoned) => {
                             #[cfg(debug_assertions)]
@@ -238,62 +329,49 @@ impl Net {
                         }
                     };
 
-                    let mut bbox = Rect::default();
-                    let success = match locked.update(&frame, &mut bbox) {
-                        Ok(s) => s,
+                    match locked.update(frame) {
+                        Ok(Some(bbox)) => (true, bbox),
+                        Ok(None) => (false, Rect::default()),
                         Err(e) => {
                             error!("Tracker update failed: {}", e);
-                            false
+                            (false, Rect::default())
                         }
-                    };
-                    (success, bbox)
+                    }
                 };
 
-                (success, bbox, tracker)
+                (success, bbox, tracker, label)
             })
             .collect();
 
         // Filter successful trackers
         let mut valid_trackers = Vec::new();
         let mut current_rects = Vec::new();
+        let mut current_labels = Vec::new();
 
-        for (success, bbox, tracker) in results {
+        for (success, bbox, tracker, label) in results {
             if success {
                 #[cfg(debug_assertions)]
                 debug!("Succeeded updating trackers");
                 valid_trackers.push(tracker);
                 current_rects.push(bbox);
+                current_labels.push(label);
             }
         }
 
         self.trackers = valid_trackers;
         self.tracked_rects = current_rects;
+        self.tracked_labels = current_labels;
         Ok(())
     }
 
     pub fn draw_tracking_results(&self, frame: &mut Mat) -> Result<()> {
         debug!("drawing {:?} recs", self.tracked_rects);
-        // Compute scale factors from small (input_size) to full frame
-        let fx = frame.cols() as f32 / self.input_size.width as f32;
-        let fy = frame.rows() as f32 / self.input_size.height as f32;
-
-        imgproc::line(
-            frame,
-            Point::new(0, 200),
-            Point::new(frame.cols(), 200),
-            Scalar::new(0., 255., 0., 0.),
-            2,
-            imgproc::LINE_8,
-            0,
-        )?;
+
+        self.counting.draw(frame)?;
 
         for rect in &self.tracked_rects {
             debug!("Original rect (small coords): {:?}", rect);
-            let x = (rect.x as f32 * fx).round() as i32;
-            let y = (rect.y as f32 * fy).round() as i32;
-            let w = (rect.width as f32 * fx).round() as i32;
-            let h = (rect.height as f32 * fy).round() as i32;
-            let scaled = Rect::new(x, y, w, h);
+            let scaled = self.transform.to_frame(*rect);
             debug!("Drawing scaled rect: {:?}", scaled);
             imgproc::rectangle(
                 frame,
@@ -306,94 +384,6 @@ impl Net {
         }
         Ok(())
     }
-
-    fn detect_objects(&mut self, frame: &Mat) -> Result<Vec<(Rect, f32)>> {
-        let mut detections: Vec<(Rect, f32)> = Vec::with_capacity(100); // Pre-allocate with reasonable capacity
-
-        let blob = dnn::blob_from_image(
-            frame,
-            1. / 127.5,
-            self.input_size,
-            Scalar::new(127.5, 127.5, 127.5, 0.),
-            true,
-            false,
-            CV_32F,
-        )?;
-
-        self.net.set_input_def(&blob);
-
-        // TODO: Find out if this is the right function to use
-        let output = self.net.forward_single("detection_out")?;
-        debug!("Ran net fwd");
-
-        let sizes = output.mat_size();
-        if sizes.len() != 4 {
-            bail!(
-                "Unexpected output size. Expected: 4 Received: {}",
-                sizes.len()
-            );
-        }
-
-        let num_detections = sizes[2] as usize;
-        let mut clone = output.clone();
-        let mv = MatViewND::<f32>::new(&mut clone)?;
-        #[cfg(debug_assertions)]
-        debug!("{:?} Raw net detections", 0..num_detections);
-
-        // Parallel processing of detections
-        let detections: Vec<(Rect, f32)> = (0..num_detections)
-            .into_par_iter()
-            .filter_map(|i| {
-                // Get confidence and class_id safely
-                let confidence = mv.get(&[0, 0, i as i32, 2]).ok()?;
-                if *confidence <= self.confidence {
-                    return None;
-                }
-
-                let class_id = mv.get(&[0, 0, i as i32, 1]).ok()?;
-
-                // Check for person class
-                if (*class_id as usize) < Self::CLASSES.len()
-                    && Self::CLASSES[*class_id as usize] == "person"
-                {
-                    // Calculate bounding box inline for better performance
-                    let (w, h) = (self.input_size.width as f32, self.input_size.height as f32);
-                    let start_x = mv.get(&[0, 0, i as i32, 3]).ok().map(|v| (*v * w) as i32)?;
-                    let start_y = mv.get(&[0, 0, i as i32, 4]).ok().map(|v| (*v * h) as i32)?;
-                    let end_x = mv.get(&[0, 0, i as i32, 5]).ok().map(|v| (*v * w) as i32)?;
-                    let end_y = mv.get(&[0, 0, i as i32, 6]).ok().map(|v| (*v * h) as i32)?;
-
-                    let rect = Rect::new(
-                        start_x.max(0),
-                        start_y.max(0),
-                        (end_x - start_x).max(1),
-                        (end_y - start_y).max(1),
-                    );
-
-                    Some((rect, *confidence))
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        Ok(detections)
-    }
-
-    fn get_bounding_box(&self, mv: &MatViewND<f32>, idx: usize) -> Result<Rect> {
-        let (w, h) = (self.input_size.width as f32, self.input_size.height as f32);
-        let start_x = (mv.get(&[0, 0, idx as i32, 3])? * w) as i32;
-        let start_y = (mv.get(&[0, 0, idx as i32, 4])? * h) as i32;
-        let end_x = (mv.get(&[0, 0, idx as i32, 5])? * w) as i32;
-        let end_y = (mv.get(&[0, 0, idx as i32, 6])? * h) as i32;
-
-        Ok(Rect::new(
-            start_x.max(0),
-            start_y.max(0),
-            (end_x - start_x).max(1),
-            (end_y - start_y).max(1),
-        ))
-    }
 }
 
 #[derive(Debug)]
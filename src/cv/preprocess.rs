@@ -0,0 +1,336 @@
+use opencv::core::{self, BORDER_CONSTANT, Mat, Point, Rect, Scalar, Size};
+use opencv::imgproc;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::conf::PreprocessConf;
+use crate::cv::centroid::Centroid;
+use crate::cv::geometry::NormPoint;
+
+/// Gray the letterbox bars are filled with, the one YOLO models are trained with
+const PADDING: f64 = 114.;
+
+/// Clockwise rotation applied to camera frames before detection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Rotation::None),
+            "cw90" => Ok(Rotation::Cw90),
+            "cw180" => Ok(Rotation::Cw180),
+            "cw270" => Ok(Rotation::Cw270),
+            _ => Err(format!(
+                "Unknown rotation {s:?}, expected none, cw90, cw180 or cw270"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rotation::None => write!(f, "none"),
+            Rotation::Cw90 => write!(f, "cw90"),
+            Rotation::Cw180 => write!(f, "cw180"),
+            Rotation::Cw270 => write!(f, "cw270"),
+        }
+    }
+}
+
+impl Rotation {
+    fn code(self) -> Option<i32> {
+        match self {
+            Rotation::None => None,
+            Rotation::Cw90 => Some(core::ROTATE_90_CLOCKWISE),
+            Rotation::Cw180 => Some(core::ROTATE_180),
+            Rotation::Cw270 => Some(core::ROTATE_90_COUNTERCLOCKWISE),
+        }
+    }
+}
+
+/// Mirroring applied to camera frames after the rotation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Flip {
+    #[default]
+    None,
+    /// Left and right are swapped
+    Horizontal,
+    /// Top and bottom are swapped
+    Vertical,
+    Both,
+}
+
+impl FromStr for Flip {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Flip::None),
+            "horizontal" => Ok(Flip::Horizontal),
+            "vertical" => Ok(Flip::Vertical),
+            "both" => Ok(Flip::Both),
+            _ => Err(format!(
+                "Unknown flip {s:?}, expected none, horizontal, vertical or both"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Flip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Flip::None => write!(f, "none"),
+            Flip::Horizontal => write!(f, "horizontal"),
+            Flip::Vertical => write!(f, "vertical"),
+            Flip::Both => write!(f, "both"),
+        }
+    }
+}
+
+impl Flip {
+    fn code(self) -> Option<i32> {
+        match self {
+            Flip::None => None,
+            Flip::Horizontal => Some(1),
+            Flip::Vertical => Some(0),
+            Flip::Both => Some(-1),
+        }
+    }
+}
+
+/// Turns camera frames of one size into network inputs: crop, rotation,
+/// flip, then a resize that letterboxes or stretches.
+///
+/// Everything after detection works in input coordinates, [`Transform::to_frame`]
+/// maps them back into the camera frame for counting, publishing and drawing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transform {
+    /// Size of the camera frames this transform was made for
+    pub frame_size: Size,
+    crop: Rect,
+    rotation: Rotation,
+    flip: Flip,
+    /// Size of the crop once rotated
+    oriented: Size,
+    /// Size of the oriented crop once resized, without the letterbox bars
+    content: Size,
+    /// Top left corner of the content in the input
+    offset: Point,
+    input: Size,
+}
+
+impl Transform {
+    pub fn new(frame_size: Size, conf: &PreprocessConf, input: Size) -> Self {
+        let crop = match conf.crop {
+            Some([x, y, width, height]) => {
+                let (fw, fh) = (frame_size.width as f32, frame_size.height as f32);
+                let x0 = ((x * fw).round() as i32).clamp(0, frame_size.width - 1);
+                let y0 = ((y * fh).round() as i32).clamp(0, frame_size.height - 1);
+                let x1 = (((x + width) * fw).round() as i32).clamp(x0 + 1, frame_size.width);
+                let y1 = (((y + height) * fh).round() as i32).clamp(y0 + 1, frame_size.height);
+                Rect::new(x0, y0, x1 - x0, y1 - y0)
+            }
+            None => Rect::new(0, 0, frame_size.width, frame_size.height),
+        };
+
+        let oriented = match conf.rotation {
+            Rotation::Cw90 | Rotation::Cw270 => Size::new(crop.height, crop.width),
+            Rotation::None | Rotation::Cw180 => Size::new(crop.width, crop.height),
+        };
+
+        let content = if conf.letterbox {
+            let scale = (input.width as f32 / oriented.width as f32)
+                .min(input.height as f32 / oriented.height as f32);
+            Size::new(
+                ((oriented.width as f32 * scale).round() as i32).clamp(1, input.width),
+                ((oriented.height as f32 * scale).round() as i32).clamp(1, input.height),
+            )
+        } else {
+            input
+        };
+
+        Self {
+            frame_size,
+            crop,
+            rotation: conf.rotation,
+            flip: conf.flip,
+            oriented,
+            content,
+            offset: Point::new(
+                (input.width - content.width) / 2,
+                (input.height - content.height) / 2,
+            ),
+            input,
+        }
+    }
+
+    /// The network input made from `frame`, which must be of `frame_size`
+    pub fn apply(&self, frame: &Mat) -> opencv::Result<Mat> {
+        let cropped = Mat::roi(frame, self.crop)?;
+
+        let mut rotated = Mat::default();
+        let oriented = match self.rotation.code() {
+            Some(code) => {
+                core::rotate(&*cropped, &mut rotated, code)?;
+                &rotated
+            }
+            None => &*cropped,
+        };
+
+        let mut flipped = Mat::default();
+        let oriented = match self.flip.code() {
+            Some(code) => {
+                core::flip(oriented, &mut flipped, code)?;
+                &flipped
+            }
+            None => oriented,
+        };
+
+        let mut resized = Mat::default();
+        imgproc::resize(
+            oriented,
+            &mut resized,
+            self.content,
+            0.,
+            0.,
+            imgproc::INTER_AREA,
+        )?;
+        if self.content == self.input {
+            return Ok(resized);
+        }
+
+        let mut input = Mat::default();
+        core::copy_make_border(
+            &resized,
+            &mut input,
+            self.offset.y,
+            self.input.height - self.content.height - self.offset.y,
+            self.offset.x,
+            self.input.width - self.content.width - self.offset.x,
+            BORDER_CONSTANT,
+            Scalar::all(PADDING),
+        )?;
+        Ok(input)
+    }
+
+    /// Maps a point of the input into the camera frame
+    pub fn to_frame_point(&self, x: f32, y: f32) -> (f32, f32) {
+        // Undo the resize
+        let (w, h) = (self.oriented.width as f32, self.oriented.height as f32);
+        let x = (x - self.offset.x as f32) * w / self.content.width as f32;
+        let y = (y - self.offset.y as f32) * h / self.content.height as f32;
+
+        // Undo the flip, its own inverse
+        let (x, y) = match self.flip {
+            Flip::None => (x, y),
+            Flip::Horizontal => (w - x, y),
+            Flip::Vertical => (x, h - y),
+            Flip::Both => (w - x, h - y),
+        };
+
+        // Undo the rotation, back into the crop
+        let (cw, ch) = (self.crop.width as f32, self.crop.height as f32);
+        let (x, y) = match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Cw90 => (y, ch - x),
+            Rotation::Cw180 => (cw - x, ch - y),
+            Rotation::Cw270 => (cw - y, x),
+        };
+
+        (x + self.crop.x as f32, y + self.crop.y as f32)
+    }
+
+    /// Maps a box of the input into the camera frame, clipped to the crop
+    pub fn to_frame(&self, rect: Rect) -> Rect {
+        let (ax, ay) = self.to_frame_point(rect.x as f32, rect.y as f32);
+        let (bx, by) =
+            self.to_frame_point((rect.x + rect.width) as f32, (rect.y + rect.height) as f32);
+
+        let crop = self.crop;
+        let (left, right) = (crop.x, crop.x + crop.width);
+        let (top, bottom) = (crop.y, crop.y + crop.height);
+        let x0 = (ax.min(bx).round() as i32).clamp(left, right);
+        let y0 = (ay.min(by).round() as i32).clamp(top, bottom);
+        let x1 = (ax.max(bx).round() as i32).clamp(left, right);
+        let y1 = (ay.max(by).round() as i32).clamp(top, bottom);
+        Rect::new(x0, y0, x1 - x0, y1 - y0)
+    }
+
+    /// Maps a centroid of the input into normalized camera frame coordinates
+    pub fn normalize(&self, centroid: &Centroid) -> NormPoint {
+        let (x, y) = self.to_frame_point(centroid.x as f32, centroid.y as f32);
+        [
+            x / self.frame_size.width as f32,
+            y / self.frame_size.height as f32,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maps_letterboxed_rotated_crop_back_to_frame() {
+        let transform = Transform::new(
+            Size::new(1920, 1080),
+            &PreprocessConf {
+                rotation: Rotation::Cw90,
+                flip: Flip::None,
+                letterbox: true,
+                // Right half of the frame
+                crop: Some([0.5, 0.0, 0.5, 1.0]),
+            },
+            Size::new(300, 300),
+        );
+
+        // The 960x1080 crop stands up as 1080x960, letterboxed to 300x267
+        assert_eq!(transform.content, Size::new(300, 267));
+        assert_eq!(transform.offset, Point::new(0, 16));
+
+        // Top left of the content is the bottom left of the crop
+        assert_eq!(transform.to_frame_point(0., 16.), (960., 1080.));
+        // Bottom right of the content is the top right of the frame
+        assert_eq!(transform.to_frame_point(300., 283.), (1920., 0.));
+
+        // The whole content is the whole crop, the bars are clipped away
+        assert_eq!(
+            transform.to_frame(Rect::new(0, 0, 300, 300)),
+            Rect::new(960, 0, 960, 1080)
+        );
+    }
+
+    #[test]
+    fn test_stretch_without_crop_is_a_plain_scale() {
+        let transform = Transform::new(
+            Size::new(1280, 720),
+            &PreprocessConf {
+                letterbox: false,
+                ..PreprocessConf::default()
+            },
+            Size::new(320, 320),
+        );
+
+        assert_eq!(
+            transform.to_frame(Rect::new(80, 160, 40, 32)),
+            Rect::new(320, 360, 160, 72)
+        );
+        assert_eq!(
+            transform.normalize(&Centroid { x: 160, y: 80 }),
+            [0.5, 0.25]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use anyhow::{Context, Result, bail};
+use opencv::core::{CV_32F, Mat, MatTraitConst, Rect, Scalar, Size, Vector, no_array};
+use opencv::dnn::{self, NetTrait};
+use opencv::imgproc;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::conf::ReidConf;
+use crate::cv::centroid::TrackableObject;
+
+const HUE_BINS: i32 = 16;
+const SATURATION_BINS: i32 = 8;
+
+/// Weight of a new descriptor in the running appearance of a track
+const APPEARANCE_RATE: f32 = 0.2;
+
+/// Appearance descriptor used to recover tracks lost for a few frames
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReidMode {
+    #[default]
+    Off,
+    /// Hue and saturation histogram of the box
+    Histogram,
+    /// Output of an embedding network run on the box
+    Embedding,
+}
+
+impl FromStr for ReidMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(ReidMode::Off),
+            "histogram" => Ok(ReidMode::Histogram),
+            "embedding" => Ok(ReidMode::Embedding),
+            _ => Err(format!(
+                "Unknown re-identification mode {s:?}, expected off, histogram or embedding"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ReidMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReidMode::Off => write!(f, "off"),
+            ReidMode::Histogram => write!(f, "histogram"),
+            ReidMode::Embedding => write!(f, "embedding"),
+        }
+    }
+}
+
+/// Unit length appearance vector, comparable with [`Descriptor::similarity`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Descriptor(Vec<f32>);
+
+impl Descriptor {
+    pub fn new(mut values: Vec<f32>) -> Self {
+        normalize(&mut values);
+        Self(values)
+    }
+
+    /// Cosine similarity, 1 for identical appearances
+    pub fn similarity(&self, other: &Descriptor) -> f32 {
+        if self.0.len() != other.0.len() {
+            return 0.0;
+        }
+        self.0.iter().zip(&other.0).map(|(a, b)| a * b).sum()
+    }
+
+    /// Moves the descriptor towards `other` by `rate`
+    pub fn blend(&mut self, other: &Descriptor, rate: f32) {
+        if self.0.len() != other.0.len() {
+            self.0 = other.0.clone();
+            return;
+        }
+        for (value, new) in self.0.iter_mut().zip(&other.0) {
+            *value = (1.0 - rate) * *value + rate * new;
+        }
+        normalize(&mut self.0);
+    }
+}
+
+/// Computes [`Descriptor`]s for boxes of a frame
+#[derive(Debug, Clone)]
+pub enum Describer {
+    Histogram,
+    Embedding { net: dnn::Net, input_size: Size },
+}
+
+impl Describer {
+    /// The describer for `conf.mode`, `None` when re-identification is off
+    pub fn new(conf: &ReidConf) -> Result<Option<Self>> {
+        match conf.mode {
+            ReidMode::Off => Ok(None),
+            ReidMode::Histogram => Ok(Some(Describer::Histogram)),
+            ReidMode::Embedding => {
+                let Some(model) = &conf.model else {
+                    bail!("Re-identification by embedding needs a model");
+                };
+                let net = dnn::read_net_def(&model.to_string_lossy())
+                    .with_context(|| format!("Failed to load {}", model.display()))?;
+
+                Ok(Some(Describer::Embedding {
+                    net,
+                    input_size: Size::new(conf.input_width, conf.input_height),
+                }))
+            }
+        }
+    }
+
+    /// Describes the part of `frame` inside `rect`, `None` when the box is
+    /// outside of the frame
+    pub fn describe(&mut self, frame: &Mat, rect: Rect) -> opencv::Result<Option<Descriptor>> {
+        let size = frame.size()?;
+        let x = rect.x.clamp(0, size.width);
+        let y = rect.y.clamp(0, size.height);
+        let width = (rect.x + rect.width).min(size.width) - x;
+        let height = (rect.y + rect.height).min(size.height) - y;
+        if width <= 0 || height <= 0 {
+            return Ok(None);
+        }
+        let roi = Mat::roi(frame, Rect::new(x, y, width, height))?.try_clone()?;
+
+        let values = match self {
+            Describer::Histogram => {
+                let mut hsv = Mat::default();
+                imgproc::cvt_color_def(&roi, &mut hsv, imgproc::COLOR_BGR2HSV)?;
+
+                let mut hist = Mat::default();
+                imgproc::calc_hist_def(
+                    &Vector::<Mat>::from_iter([hsv]),
+                    &Vector::from_slice(&[0, 1]),
+                    &no_array(),
+                    &mut hist,
+                    &Vector::from_slice(&[HUE_BINS, SATURATION_BINS]),
+                    &Vector::from_slice(&[0., 180., 0., 256.]),
+                )?;
+                hist.data_typed::<f32>()?.to_vec()
+            }
+            Describer::Embedding { net, input_size } => {
+                let blob = dnn::blob_from_image(
+                    &roi,
+                    1. / 255.,
+                    *input_size,
+                    Scalar::default(),
+                    true,
+                    false,
+                    CV_32F,
+                )?;
+                net.set_input_def(&blob)?;
+                net.forward_single_def()?.data_typed::<f32>()?.to_vec()
+            }
+        };
+
+        Ok(Some(Descriptor::new(values)))
+    }
+}
+
+/// Recently lost tracks, kept for a while so a detection that looks the same
+/// gets its old id back
+#[derive(Debug, Clone)]
+pub struct Gallery {
+    lost: Vec<(u64, TrackableObject)>,
+    threshold: f32,
+    ttl_frames: u64,
+}
+
+impl Gallery {
+    pub fn new(conf: &ReidConf) -> Self {
+        Self {
+            lost: Vec::new(),
+            threshold: conf.threshold,
+            ttl_frames: conf.ttl_frames as u64,
+        }
+    }
+
+    /// Keeps `obj`, lost on `frame`, if it has an appearance to match on
+    pub fn insert(&mut self, obj: TrackableObject, frame: u64) {
+        if obj.appearance.is_some() {
+            self.lost.push((frame, obj));
+        }
+    }
+
+    /// Forgets tracks lost for longer than the time to live
+    pub fn expire(&mut self, frame: u64) {
+        let ttl = self.ttl_frames;
+        self.lost.retain(|(lost_at, _)| frame - lost_at <= ttl);
+    }
+
+    /// Takes the lost track of class `label` that looks most like `descriptor`
+    pub fn recover(&mut self, label: &str, descriptor: &Descriptor) -> Option<TrackableObject> {
+        let (idx, _) = self
+            .lost
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, obj))| obj.label == label)
+            .filter_map(|(idx, (_, obj))| {
+                let similarity = obj.appearance.as_ref()?.similarity(descriptor);
+                (similarity >= self.threshold).then_some((idx, similarity))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+        Some(self.lost.swap_remove(idx).1)
+    }
+}
+
+/// Updates the running appearance of a track with a new descriptor
+pub fn observe(appearance: &mut Option<Descriptor>, descriptor: &Descriptor) {
+    match appearance {
+        Some(appearance) => appearance.blend(descriptor, APPEARANCE_RATE),
+        None => *appearance = Some(descriptor.clone()),
+    }
+}
+
+fn normalize(values: &mut [f32]) {
+    let norm = values.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        values.iter_mut().for_each(|v| *v /= norm);
+    }
+}
@@ -0,0 +1,361 @@
+use anyhow::{Result, bail};
+use opencv::core::{Mat, Ptr, Rect, Rect2d};
+use opencv::tracking::{
+    TrackerCSRT, TrackerCSRT_Params, TrackerKCF, TrackerKCF_Params, legacy_TrackerMOSSE,
+    legacy_TrackerTrait,
+};
+use opencv::video::{TrackerMIL, TrackerMIL_Params, TrackerTrait};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::cv::geometry::iou;
+
+/// Follows a single object between detections
+pub trait ObjectTracker: Send {
+    /// Starts following the object inside `rect`
+    fn init(&mut self, frame: &Mat, rect: Rect) -> Result<()>;
+
+    /// Locates the object in `frame`, `None` once it is lost
+    fn update(&mut self, frame: &Mat) -> Result<Option<Rect>>;
+}
+
+impl fmt::Debug for dyn ObjectTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ObjectTracker")
+    }
+}
+
+/// Single object tracker run on the frames between two detections
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TrackerBackend {
+    Kcf(KcfParams),
+    Csrt(CsrtParams),
+    /// Fastest, from the legacy tracking API
+    Mosse,
+    Mil(MilParams),
+    /// No tracker, the model runs on every frame
+    None,
+}
+
+impl Default for TrackerBackend {
+    fn default() -> Self {
+        TrackerBackend::Kcf(KcfParams::default())
+    }
+}
+
+/// Picks a backend by name with its default parameters
+impl FromStr for TrackerBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "kcf" => Ok(TrackerBackend::Kcf(KcfParams::default())),
+            "csrt" => Ok(TrackerBackend::Csrt(CsrtParams::default())),
+            "mosse" => Ok(TrackerBackend::Mosse),
+            "mil" => Ok(TrackerBackend::Mil(MilParams::default())),
+            "none" => Ok(TrackerBackend::None),
+            _ => Err(format!(
+                "Unknown tracker {s:?}, expected kcf, csrt, mosse, mil or none"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TrackerBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrackerBackend::Kcf(_) => write!(f, "kcf"),
+            TrackerBackend::Csrt(_) => write!(f, "csrt"),
+            TrackerBackend::Mosse => write!(f, "mosse"),
+            TrackerBackend::Mil(_) => write!(f, "mil"),
+            TrackerBackend::None => write!(f, "none"),
+        }
+    }
+}
+
+impl TrackerBackend {
+    /// A new tracker, `None` for [`TrackerBackend::None`]
+    pub fn create(&self) -> Result<Option<Box<dyn ObjectTracker>>> {
+        let tracker: Box<dyn ObjectTracker> = match self {
+            TrackerBackend::Kcf(params) => {
+                let mut kcf = TrackerKCF_Params::default()?;
+                kcf.detect_thresh = params.detect_thresh;
+                kcf.sigma = params.sigma;
+                kcf.lambda = params.lambda;
+                kcf.interp_factor = params.interp_factor;
+                Box::new(Tracker(TrackerKCF::create(kcf)?))
+            }
+            TrackerBackend::Csrt(params) => {
+                let mut csrt = TrackerCSRT_Params::default()?;
+                csrt.set_use_hog(params.use_hog);
+                csrt.set_use_color_names(params.use_color_names);
+                csrt.set_padding(params.padding);
+                csrt.set_template_size(params.template_size);
+                csrt.set_psr_threshold(params.psr_threshold);
+                csrt.set_filter_lr(params.filter_lr);
+                Box::new(Tracker(TrackerCSRT::create(&csrt)?))
+            }
+            TrackerBackend::Mosse => Box::new(Mosse(legacy_TrackerMOSSE::create()?)),
+            TrackerBackend::Mil(params) => {
+                let mut mil = TrackerMIL_Params::default()?;
+                mil.sampler_init_in_radius = params.init_radius;
+                mil.sampler_search_win_size = params.search_window;
+                mil.sampler_track_in_radius = params.track_radius;
+                mil.feature_set_num_features = params.features;
+                Box::new(Tracker(TrackerMIL::create(mil)?))
+            }
+            TrackerBackend::None => return Ok(None),
+        };
+
+        Ok(Some(tracker))
+    }
+
+    pub fn is_none(&self) -> bool {
+        matches!(self, TrackerBackend::None)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KcfParams {
+    /// Smallest response for the object to count as found
+    pub detect_thresh: f32,
+    /// Gaussian kernel bandwidth
+    pub sigma: f32,
+    /// Regularization
+    pub lambda: f32,
+    /// Adaptation rate of the model, higher follows lighting changes faster
+    pub interp_factor: f32,
+}
+
+impl Default for KcfParams {
+    fn default() -> Self {
+        Self {
+            detect_thresh: 0.5,
+            sigma: 0.2,
+            lambda: 0.0001,
+            interp_factor: 0.075,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsrtParams {
+    pub use_hog: bool,
+    pub use_color_names: bool,
+    /// Search area around the object, relative to its size
+    pub padding: f32,
+    /// Size in pixels the object is scaled to
+    pub template_size: f32,
+    /// Smallest peak to sidelobe ratio for the object to count as found
+    pub psr_threshold: f32,
+    /// Learning rate of the filter
+    pub filter_lr: f32,
+}
+
+impl Default for CsrtParams {
+    fn default() -> Self {
+        Self {
+            use_hog: true,
+            use_color_names: true,
+            padding: 3.,
+            template_size: 200.,
+            psr_threshold: 0.035,
+            filter_lr: 0.02,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MilParams {
+    /// Radius in pixels for positive samples on the first frame
+    pub init_radius: f32,
+    /// Search window size in pixels
+    pub search_window: f32,
+    /// Radius in pixels for positive samples while tracking
+    pub track_radius: f32,
+    /// Number of Haar features
+    pub features: i32,
+}
+
+impl Default for MilParams {
+    fn default() -> Self {
+        Self {
+            init_radius: 3.,
+            search_window: 25.,
+            track_radius: 4.,
+            features: 250,
+        }
+    }
+}
+
+/// What happens to each live tracker and each detection on a detection frame
+#[derive(Debug, Default, PartialEq)]
+pub struct Reconciliation {
+    /// Tracker and detection of the same object, and whether the tracker
+    /// drifted far enough from the detection to be restarted
+    pub matched: Vec<(usize, usize, bool)>,
+    /// Trackers no detection supports
+    pub missed: Vec<usize>,
+    /// Detections no tracker follows yet
+    pub new: Vec<usize>,
+}
+
+/// Pairs `tracked` boxes with `detections` of the same label overlapping by
+/// at least `match_iou`, best overlaps first. Pairs overlapping by less than
+/// `reinit_iou` are marked as drifted.
+pub fn reconcile(
+    tracked: &[Rect],
+    tracked_labels: &[String],
+    detections: &[Rect],
+    labels: &[String],
+    match_iou: f32,
+    reinit_iou: f32,
+) -> Reconciliation {
+    let mut pairs: Vec<(f32, usize, usize)> = tracked
+        .iter()
+        .enumerate()
+        .flat_map(|(t, a)| {
+            detections
+                .iter()
+                .enumerate()
+                .map(move |(d, b)| (iou(*a, *b), t, d))
+        })
+        .filter(|(overlap, t, d)| *overlap >= match_iou && tracked_labels[*t] == labels[*d])
+        .collect();
+    pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut used_tracked = HashSet::new();
+    let mut used_detections = HashSet::new();
+    let mut reconciliation = Reconciliation::default();
+    for (overlap, t, d) in pairs {
+        if used_tracked.contains(&t) || used_detections.contains(&d) {
+            continue;
+        }
+        used_tracked.insert(t);
+        used_detections.insert(d);
+        reconciliation.matched.push((t, d, overlap < reinit_iou));
+    }
+
+    reconciliation.missed = (0..tracked.len())
+        .filter(|t| !used_tracked.contains(t))
+        .collect();
+    reconciliation.new = (0..detections.len())
+        .filter(|d| !used_detections.contains(d))
+        .collect();
+
+    reconciliation
+}
+
+/// Trackers of the current tracking API
+struct Tracker<T>(Ptr<T>);
+
+impl<T> ObjectTracker for Tracker<T>
+where
+    Ptr<T>: TrackerTrait + Send,
+{
+    fn init(&mut self, frame: &Mat, rect: Rect) -> Result<()> {
+        self.0.init(frame, rect)?;
+        Ok(())
+    }
+
+    fn update(&mut self, frame: &Mat) -> Result<Option<Rect>> {
+        let mut bbox = Rect::default();
+        Ok(self.0.update(frame, &mut bbox)?.then_some(bbox))
+    }
+}
+
+struct Mosse(Ptr<legacy_TrackerMOSSE>);
+
+impl ObjectTracker for Mosse {
+    fn init(&mut self, frame: &Mat, rect: Rect) -> Result<()> {
+        let rect = Rect2d::new(
+            rect.x as f64,
+            rect.y as f64,
+            rect.width as f64,
+            rect.height as f64,
+        );
+        if !self.0.init(frame, rect)? {
+            bail!("MOSSE tracker rejected {:?}", rect);
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, frame: &Mat) -> Result<Option<Rect>> {
+        let mut bbox = Rect2d::default();
+        if !self.0.update(frame, &mut bbox)? {
+            return Ok(None);
+        }
+
+        Ok(Some(Rect::new(
+            bbox.x.round() as i32,
+            bbox.y.round() as i32,
+            bbox.width.round() as i32,
+            bbox.height.round() as i32,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconcile_keeps_restarts_retires_and_starts() {
+        let tracked = [
+            Rect::new(0, 0, 40, 80),
+            Rect::new(100, 0, 40, 80),
+            Rect::new(200, 0, 40, 80),
+        ];
+        let detections = [
+            // Drifted from the second tracker
+            Rect::new(112, 6, 40, 80),
+            // Right on the first one
+            Rect::new(2, 0, 40, 80),
+            // Someone new
+            Rect::new(300, 0, 40, 80),
+        ];
+
+        let people = vec!["person".to_string(); 3];
+
+        let reconciliation = reconcile(&tracked, &people, &detections, &people, 0.3, 0.7);
+
+        assert_eq!(
+            reconciliation,
+            Reconciliation {
+                matched: vec![(0, 1, false), (1, 0, true)],
+                missed: vec![2],
+                new: vec![2],
+            }
+        );
+    }
+
+    #[test]
+    fn test_reconcile_only_pairs_boxes_of_the_same_class() {
+        let tracked = [Rect::new(0, 0, 40, 80)];
+        let detections = [Rect::new(0, 0, 40, 80), Rect::new(2, 0, 40, 80)];
+
+        let reconciliation = reconcile(
+            &tracked,
+            &["person".into()],
+            &detections,
+            &["bicycle".into(), "person".into()],
+            0.3,
+            0.7,
+        );
+
+        assert_eq!(
+            reconciliation,
+            Reconciliation {
+                matched: vec![(0, 1, false)],
+                missed: vec![],
+                new: vec![0],
+            }
+        );
+    }
+}
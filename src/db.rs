@@ -1,6 +1,326 @@
-use sea_orm::Database;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use dirs::data_dir;
+use log::{debug, info};
 use migration::{Migrator, MigratorTrait};
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, ConnectionTrait, Database, DatabaseConnection, EntityTrait,
+    QueryFilter, TransactionTrait,
+};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+use uuid::Uuid;
 
-pub fn run_migration() {
+use crate::cv::CvDetection;
+use crate::direction::Direction;
+use crate::entities::{area_adjustment, crossing, delivery, door_count, entry, tag_read};
+use crate::proc::FusedEvent;
+use crate::rfid::TagDetection;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeliveryStatus {
+    Queued,
+    Delivered,
+    Rejected,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Queued => "queued",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Rejected => "rejected",
+        }
+    }
+}
+
+/// Embedded SQLite database in the data dir
+pub fn default_db_conn() -> Result<String> {
+    let path = default_db_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    Ok(format!("sqlite://{}?mode=rwc", path.display()))
+}
+
+/// Embedded database used when `db_conn` is empty
+pub fn default_db_path() -> Result<PathBuf> {
+    Ok(data_dir()
+        .context("No data directory for this user")?
+        .join("vista")
+        .join("vista.db"))
+}
+
+/// Connects to `db_conn`, or the embedded database when it is empty, and
+/// applies pending migrations
+pub async fn run_migration(db_conn: &str) -> Result<DatabaseConnection> {
+    let db_conn = match db_conn {
+        "" => default_db_conn()?,
+        db_conn => db_conn.to_owned(),
+    };
+
+    let db = Database::connect(&db_conn)
+        .await
+        .context("Failed to connect to the database")?;
+    Migrator::up(&db, None)
+        .await
+        .context("Failed to migrate the database")?;
+
+    Ok(db)
+}
+
+/// Connects to `db_conn`, or the embedded database when it is empty, and
+/// lists the migrations it is missing without changing anything
+pub async fn pending_migrations(db_conn: &str) -> Result<(DatabaseConnection, Vec<String>)> {
+    let db_conn = match db_conn {
+        // Opened without `mode=rwc`, so a missing database is not created
+        "" => format!("sqlite://{}?mode=rw", default_db_path()?.display()),
+        db_conn => db_conn.to_owned(),
+    };
+
+    let db = Database::connect(&db_conn)
+        .await
+        .context("Failed to connect to the database")?;
+
+    // Listing them creates the migration table if missing, roll that back
+    let txn = db.begin().await?;
+    let pending = Migrator::get_pending_migrations(&txn)
+        .await
+        .context("Failed to read the applied migrations")?
+        .iter()
+        .map(|migration| migration.name().to_owned())
+        .collect();
+    txn.rollback().await?;
+
+    Ok((db, pending))
+}
+
+/// Every crossing, tag read, fused event and delivery the system produces
+#[derive(Clone)]
+pub struct EventStore {
+    db: DatabaseConnection,
+}
+
+impl EventStore {
+    pub async fn open(db_conn: &str) -> Result<Self> {
+        let db = run_migration(db_conn).await?;
+        info!("Event store ready ({:?})", db.get_database_backend());
+
+        Ok(Self { db })
+    }
+
+    pub async fn record_crossing(&self, crossing: &CvDetection) -> Result<()> {
+        let bbox = crossing.bbox();
+        crossing::Entity::insert(crossing::ActiveModel {
+            object_id: Set(crossing.oid() as i32),
+            label: Set(crossing.label().into()),
+            direction: Set(crossing.direction().as_action().into()),
+            x: Set(bbox.x),
+            y: Set(bbox.y),
+            width: Set(bbox.width),
+            height: Set(bbox.height),
+            time: Set(wall_clock(crossing.instant())),
+            ..Default::default()
+        })
+        .exec(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn record_tag_read(&self, tag: &TagDetection) -> Result<()> {
+        tag_read::Entity::insert(tag_read::ActiveModel {
+            tag: Set(tag.tag().into()),
+            antenna: Set(tag.antenna()),
+            rssi: Set(tag.rssi()),
+            time: Set(wall_clock(tag.time())),
+            ..Default::default()
+        })
+        .exec(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn record_entry(&self, event_id: Uuid, event: &FusedEvent) -> Result<()> {
+        let (kind, tag, direction) = match event {
+            FusedEvent::Person { tag, direction, .. } => {
+                ("person", Some(tag.clone()), Some(direction.as_action()))
+            }
+            FusedEvent::Anonymous { direction, .. } => {
+                ("anonymous", None, Some(direction.as_action()))
+            }
+            FusedEvent::TagOnly { tag, .. } => ("tag_only", Some(tag.clone()), None),
+        };
+
+        entry::Entity::insert(entry::ActiveModel {
+            event_id: Set(event_id.to_string()),
+            kind: Set(kind.into()),
+            tag: Set(tag),
+            direction: Set(direction.map(Into::into)),
+            time: Set(wall_clock(event.instant())),
+            ..Default::default()
+        })
+        .exec(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stores the latest delivery state of `event_id`
+    pub async fn record_delivery(
+        &self,
+        event_id: Uuid,
+        status: DeliveryStatus,
+        entry_id: Option<&str>,
+        reason: Option<&str>,
+    ) -> Result<()> {
+        debug!("Event {} {}", event_id, status.as_str());
+
+        delivery::Entity::insert(delivery::ActiveModel {
+            event_id: Set(event_id.to_string()),
+            status: Set(status.as_str().into()),
+            entry_id: Set(entry_id.map(Into::into)),
+            reason: Set(reason.map(Into::into)),
+            updated_at: Set(Utc::now()),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::column(delivery::Column::EventId)
+                .update_columns([
+                    delivery::Column::Status,
+                    delivery::Column::EntryId,
+                    delivery::Column::Reason,
+                    delivery::Column::UpdatedAt,
+                ])
+                .to_owned(),
+        )
+        .exec(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Adds a crossing to the counts of `door`, after starting them over
+    /// when they were last reset before `reset`
+    pub async fn count_crossing(
+        &self,
+        door: &str,
+        direction: Direction,
+        reset: DateTime<Utc>,
+    ) -> Result<door_count::Model> {
+        let column = match direction {
+            Direction::Up => door_count::Column::Entered,
+            Direction::Down => door_count::Column::Exited,
+            Direction::Stationary => bail!("Stationary tracks cross no door"),
+        };
+
+        // One transaction, so instances sharing the door never reset the
+        // counts another one just added to
+        let txn = self.db.begin().await?;
+
+        door_count::Entity::insert(door_count::ActiveModel {
+            door: Set(door.into()),
+            entered: Set(0),
+            exited: Set(0),
+            since: Set(Utc::now()),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::column(door_count::Column::Door)
+                .do_nothing()
+                .to_owned(),
+        )
+        .exec_without_returning(&txn)
+        .await?;
+
+        let stale = door_count::Entity::update_many()
+            .col_expr(door_count::Column::Entered, Expr::value(0))
+            .col_expr(door_count::Column::Exited, Expr::value(0))
+            .col_expr(door_count::Column::Since, Expr::value(Utc::now()))
+            .filter(door_count::Column::Door.eq(door))
+            .filter(door_count::Column::Since.lt(reset))
+            .exec(&txn)
+            .await?;
+        if stale.rows_affected > 0 {
+            info!("Counts of door {} started over", door);
+        }
+
+        door_count::Entity::update_many()
+            .col_expr(column, Expr::col(column).add(1))
+            .filter(door_count::Column::Door.eq(door))
+            .exec(&txn)
+            .await?;
+
+        let count = door_count::Entity::find()
+            .filter(door_count::Column::Door.eq(door))
+            .one(&txn)
+            .await?
+            .with_context(|| format!("No counts for door {}", door))?;
+        txn.commit().await?;
+
+        Ok(count)
+    }
+
+    /// Sets the counts of `door` back to zero
+    pub async fn reset_door(&self, door: &str) -> Result<()> {
+        door_count::Entity::insert(door_count::ActiveModel {
+            door: Set(door.into()),
+            entered: Set(0),
+            exited: Set(0),
+            since: Set(Utc::now()),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::column(door_count::Column::Door)
+                .update_columns([
+                    door_count::Column::Entered,
+                    door_count::Column::Exited,
+                    door_count::Column::Since,
+                ])
+                .to_owned(),
+        )
+        .exec_without_returning(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn door_counts(&self) -> Result<Vec<door_count::Model>> {
+        Ok(door_count::Entity::find().all(&self.db).await?)
+    }
+
+    /// Stores the correction added to the people inside `area`
+    pub async fn set_area_adjustment(&self, area: &str, adjustment: i64) -> Result<()> {
+        area_adjustment::Entity::insert(area_adjustment::ActiveModel {
+            area: Set(area.into()),
+            adjustment: Set(adjustment),
+            since: Set(Utc::now()),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::column(area_adjustment::Column::Area)
+                .update_columns([
+                    area_adjustment::Column::Adjustment,
+                    area_adjustment::Column::Since,
+                ])
+                .to_owned(),
+        )
+        .exec_without_returning(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn area_adjustments(&self) -> Result<Vec<area_adjustment::Model>> {
+        Ok(area_adjustment::Entity::find().all(&self.db).await?)
+    }
+}
+
+/// Wall clock time at which `instant` happened
+fn wall_clock(instant: Instant) -> DateTime<Utc> {
+    Utc::now() - instant.elapsed()
 }
@@ -2,4 +2,17 @@
 pub enum Direction {
     Up,
     Down,
+    /// Standing still or not tracked for long enough to tell
+    Stationary,
+}
+
+impl Direction {
+    /// Attendance action for a crossing in this direction
+    pub fn as_action(&self) -> &'static str {
+        match self {
+            Direction::Up => "entered",
+            Direction::Down => "exited",
+            Direction::Stationary => "stood",
+        }
+    }
 }
@@ -0,0 +1,20 @@
+use sea_orm::entity::prelude::*;
+
+/// Correction an administrator made to the number of people inside an area
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "area_adjustments")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    pub area: String,
+    /// Added to the people its doors let in minus those they let out
+    pub adjustment: i64,
+    /// When the correction was made, it is dropped by the next reset
+    pub since: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
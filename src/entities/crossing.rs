@@ -0,0 +1,24 @@
+use sea_orm::entity::prelude::*;
+
+/// A line or zone crossing reported by the camera
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "crossings")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// Tracker id of the object that crossed
+    pub object_id: i32,
+    /// Label of the class of the object
+    pub label: String,
+    pub direction: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub time: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
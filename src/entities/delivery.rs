@@ -0,0 +1,23 @@
+use sea_orm::entity::prelude::*;
+
+/// Latest API delivery state of an entry
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "deliveries")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    pub event_id: String,
+    /// One of queued, delivered or rejected
+    pub status: String,
+    /// Id the server assigned to the entry once delivered
+    pub entry_id: Option<String>,
+    /// Why the server rejected the entry
+    pub reason: Option<String>,
+    pub updated_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
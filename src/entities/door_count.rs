@@ -0,0 +1,20 @@
+use sea_orm::entity::prelude::*;
+
+/// Crossings of a door since its counts were last reset
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "door_counts")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    pub door: String,
+    pub entered: i64,
+    pub exited: i64,
+    /// When the counts were last reset
+    pub since: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
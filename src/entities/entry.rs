@@ -0,0 +1,22 @@
+use sea_orm::entity::prelude::*;
+
+/// A fused event, see [`crate::proc::FusedEvent`]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "entries")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// Event id sent to the API, links the entry to its delivery
+    #[sea_orm(unique)]
+    pub event_id: String,
+    /// One of person, anonymous or tag_only
+    pub kind: String,
+    pub tag: Option<String>,
+    pub direction: Option<String>,
+    pub time: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
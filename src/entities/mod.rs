@@ -0,0 +1,6 @@
+pub mod area_adjustment;
+pub mod crossing;
+pub mod delivery;
+pub mod door_count;
+pub mod entry;
+pub mod tag_read;
@@ -0,0 +1,18 @@
+use sea_orm::entity::prelude::*;
+
+/// An RFID tag read accepted by the tag filters
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "tag_reads")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub tag: String,
+    pub antenna: i32,
+    pub rssi: i32,
+    pub time: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,281 @@
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local, NaiveTime, TimeZone, Utc};
+use log::{debug, info, warning};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::conf::{AreaConf, OccupancyConf};
+use crate::cv::CvDetection;
+use crate::db::EventStore;
+use crate::direction::Direction;
+use crate::entities::{area_adjustment, door_count};
+
+/// Entries, exits and people inside since the last reset
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Count {
+    pub entered: i64,
+    pub exited: i64,
+    pub inside: i64,
+}
+
+/// Counts of every door and area
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub doors: BTreeMap<String, Count>,
+    pub areas: BTreeMap<String, Count>,
+}
+
+/// Running counts of the door this camera watches and of the areas it leads
+/// into.
+///
+/// Counts live in the event store, so they survive restarts and instances
+/// watching other doors of an area can share them through the database.
+pub struct Occupancy {
+    store: EventStore,
+    door: String,
+    classes: Vec<String>,
+    reset_at: Option<NaiveTime>,
+    areas: Vec<AreaConf>,
+}
+
+impl Occupancy {
+    pub fn new(conf: &OccupancyConf, store: EventStore) -> Result<Self> {
+        let reset_at = conf
+            .reset_at
+            .as_deref()
+            .map(|time| {
+                NaiveTime::parse_from_str(time, "%H:%M")
+                    .with_context(|| format!("Invalid reset time {time:?}, expected HH:MM"))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            store,
+            door: conf.door.clone(),
+            classes: conf.classes.clone(),
+            reset_at,
+            areas: conf.areas.clone(),
+        })
+    }
+
+    /// Counts `crossing` if it is one of an occupant, warning when an area it
+    /// leads into goes below zero or over capacity
+    pub async fn record(&self, crossing: &CvDetection) -> Result<()> {
+        if !self.classes.iter().any(|label| label == crossing.label()) {
+            return Ok(());
+        }
+
+        let direction = crossing.direction();
+        let door = self
+            .store
+            .count_crossing(&self.door, direction, self.last_reset())
+            .await?;
+        debug!(
+            "Door {}: {} entered, {} exited",
+            door.door, door.entered, door.exited
+        );
+
+        let snapshot = self.snapshot().await?;
+        for area in self.areas.iter().filter(|a| a.doors.contains(&self.door)) {
+            let inside = snapshot.areas[&area.name].inside;
+            match direction {
+                Direction::Up => {
+                    if let Some(capacity) = area.capacity
+                        && inside > capacity
+                    {
+                        warning!(
+                            "{} people inside {}, over its capacity of {}",
+                            inside,
+                            area.name,
+                            capacity
+                        );
+                    }
+                }
+                Direction::Down => {
+                    if inside < 0 {
+                        warning!(
+                            "{} people inside {}, more left than entered",
+                            inside,
+                            area.name
+                        );
+                    }
+                }
+                Direction::Stationary => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn snapshot(&self) -> Result<Snapshot> {
+        let doors = self.store.door_counts().await?;
+        let adjustments = self.store.area_adjustments().await?;
+
+        Ok(tally(&doors, &adjustments, &self.areas, self.last_reset()))
+    }
+
+    /// Sets the number of people inside `area` until the next reset
+    pub async fn correct(&self, area: &str, inside: i64) -> Result<()> {
+        let doors = self.store.door_counts().await?;
+        let unadjusted = tally(&doors, &[], &self.areas, self.last_reset());
+        let Some(count) = unadjusted.areas.get(area) else {
+            bail!("Unknown area {:?}", area);
+        };
+
+        self.store
+            .set_area_adjustment(area, inside - count.inside)
+            .await?;
+        info!("Corrected {} to {} people inside", area, inside);
+
+        Ok(())
+    }
+
+    /// Starts the counts of this door and of every area over
+    pub async fn reset(&self) -> Result<()> {
+        let doors: BTreeSet<&String> = self
+            .areas
+            .iter()
+            .flat_map(|area| &area.doors)
+            .chain([&self.door])
+            .collect();
+        for door in doors {
+            self.store.reset_door(door).await?;
+        }
+        for area in &self.areas {
+            self.store.set_area_adjustment(&area.name, 0).await?;
+        }
+        info!("Occupancy counts started over");
+
+        Ok(())
+    }
+
+    fn last_reset(&self) -> DateTime<Utc> {
+        match self.reset_at {
+            Some(at) => last_reset(at, Local::now()),
+            None => DateTime::UNIX_EPOCH,
+        }
+    }
+}
+
+/// Last time the clock of `now` showed `at`
+fn last_reset<Tz: TimeZone>(at: NaiveTime, now: DateTime<Tz>) -> DateTime<Utc> {
+    let today = now.date_naive();
+    let day = if now.time() < at {
+        today.pred_opt().unwrap_or(today)
+    } else {
+        today
+    };
+    let local = day.and_time(at);
+
+    match now.timezone().from_local_datetime(&local).earliest() {
+        Some(reset) => reset.with_timezone(&Utc),
+        // `at` was skipped by a clock change that day, close enough
+        None => local.and_utc(),
+    }
+}
+
+/// Counts of `doors` since `reset` and of the `areas` they lead into
+fn tally(
+    doors: &[door_count::Model],
+    adjustments: &[area_adjustment::Model],
+    areas: &[AreaConf],
+    reset: DateTime<Utc>,
+) -> Snapshot {
+    let mut snapshot = Snapshot::default();
+
+    for door in doors {
+        // Doors nobody crossed since the reset still hold the counts of before
+        let count = if door.since < reset {
+            Count::default()
+        } else {
+            Count {
+                entered: door.entered,
+                exited: door.exited,
+                inside: door.entered - door.exited,
+            }
+        };
+        snapshot.doors.insert(door.door.clone(), count);
+    }
+
+    for area in areas {
+        let mut count = Count::default();
+        for door in area
+            .doors
+            .iter()
+            .filter_map(|door| snapshot.doors.get(door))
+        {
+            count.entered += door.entered;
+            count.exited += door.exited;
+        }
+        let adjustment: i64 = adjustments
+            .iter()
+            .filter(|a| a.area == area.name && a.since >= reset)
+            .map(|a| a.adjustment)
+            .sum();
+        count.inside = count.entered - count.exited + adjustment;
+        snapshot.areas.insert(area.name.clone(), count);
+    }
+
+    snapshot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().to_utc()
+    }
+
+    fn door(name: &str, entered: i64, exited: i64, since: &str) -> door_count::Model {
+        door_count::Model {
+            id: 0,
+            door: name.into(),
+            entered,
+            exited,
+            since: at(since),
+        }
+    }
+
+    #[test]
+    fn test_last_reset_is_yesterday_before_the_time() {
+        let midnight = NaiveTime::from_hms_opt(0, 0, 0).unwrap();
+        let six = NaiveTime::from_hms_opt(6, 0, 0).unwrap();
+        let now = at("2026-10-17T05:30:00Z");
+
+        assert_eq!(last_reset(midnight, now), at("2026-10-17T00:00:00Z"));
+        assert_eq!(last_reset(six, now), at("2026-10-16T06:00:00Z"));
+    }
+
+    #[test]
+    fn test_area_combines_its_doors_since_the_reset() {
+        let doors = [
+            door("front", 10, 4, "2026-10-17T00:00:00Z"),
+            door("back", 3, 5, "2026-10-17T01:00:00Z"),
+            // Not crossed since the reset
+            door("dock", 7, 0, "2026-10-16T12:00:00Z"),
+        ];
+        let adjustments = [area_adjustment::Model {
+            id: 0,
+            area: "shop".into(),
+            adjustment: -2,
+            since: at("2026-10-17T02:00:00Z"),
+        }];
+        let areas = [AreaConf {
+            name: "shop".into(),
+            doors: vec!["front".into(), "back".into(), "dock".into()],
+            capacity: None,
+        }];
+
+        let snapshot = tally(&doors, &adjustments, &areas, at("2026-10-17T00:00:00Z"));
+
+        assert_eq!(snapshot.doors["dock"], Count::default());
+        assert_eq!(
+            snapshot.areas["shop"],
+            Count {
+                entered: 13,
+                exited: 9,
+                inside: 2,
+            }
+        );
+    }
+}
@@ -1,8 +1,417 @@
+use log::{debug, info, warning};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
+use tokio::time::interval;
 
+use crate::conf::FusionConf;
+use crate::db::EventStore;
+use crate::occupancy::Occupancy;
 use crate::{cv::CvDetection, direction::Direction, rfid::TagDetection};
 
-async fn proc_detections(mut rfid_rx: mpsc::Receiver<TagDetection>, mut cv_rx: mpsc::Receiver<CvDetection>) {
+#[derive(Debug, Clone)]
+pub struct FusionConfig {
+    /// Maximum distance in time between a tag read and a crossing for them to be linked
+    pub window: Duration,
+    /// Labels of the classes whose crossings are fused, the others are ignored
+    pub classes: Vec<String>,
+}
+
+impl From<&FusionConf> for FusionConfig {
+    fn from(conf: &FusionConf) -> Self {
+        Self {
+            window: Duration::from_millis(conf.window_ms),
+            classes: conf.classes.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FusedEvent {
+    /// A crossing linked to a tag read
+    Person {
+        tag: String,
+        direction: Direction,
+        instant: Instant,
+    },
+    /// A crossing with no tag read inside the window
+    Anonymous {
+        direction: Direction,
+        instant: Instant,
+    },
+    /// A tag read with no crossing inside the window
+    TagOnly { tag: String, instant: Instant },
+}
+
+impl FusedEvent {
+    /// When the crossing, or the tag read for [`FusedEvent::TagOnly`], happened
+    pub fn instant(&self) -> Instant {
+        match self {
+            FusedEvent::Person { instant, .. }
+            | FusedEvent::Anonymous { instant, .. }
+            | FusedEvent::TagOnly { instant, .. } => *instant,
+        }
+    }
+}
+
+impl Display for FusedEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FusedEvent::Person {
+                tag,
+                direction,
+                instant,
+            } => write!(
+                f,
+                "Person {} {} {:?} ago",
+                tag,
+                direction.as_action(),
+                instant.elapsed()
+            ),
+            FusedEvent::Anonymous { direction, instant } => write!(
+                f,
+                "Anonymous person {} {:?} ago",
+                direction.as_action(),
+                instant.elapsed()
+            ),
+            FusedEvent::TagOnly { tag, instant } => write!(
+                f,
+                "Tag {} seen with no crossing {:?} ago",
+                tag,
+                instant.elapsed()
+            ),
+        }
+    }
+}
+
+/// Links camera crossings with RFID tag reads that happen close in time.
+///
+/// The engine is clock agnostic: callers feed it events and tell it what time
+/// it is through [`FusionEngine::flush`], so it can be driven both by the wall
+/// clock and by recorded timestamps.
+pub struct FusionEngine {
+    window: Duration,
+    classes: Vec<String>,
+    tags: VecDeque<TagDetection>,
+    crossings: VecDeque<CvDetection>,
+    last_orphan: HashMap<String, Instant>,
+}
+
+impl FusionEngine {
+    pub fn new(config: FusionConfig) -> Self {
+        Self {
+            window: config.window,
+            classes: config.classes,
+            tags: VecDeque::new(),
+            crossings: VecDeque::new(),
+            last_orphan: HashMap::new(),
+        }
+    }
+
+    pub fn push_tag(&mut self, tag: TagDetection) {
+        let idx = self.tags.partition_point(|t| t.time() <= tag.time());
+        self.tags.insert(idx, tag);
+    }
+
+    /// Queues a crossing, unless its class is not one of the fused ones
+    pub fn push_crossing(&mut self, crossing: CvDetection) {
+        if !self.classes.iter().any(|label| label == crossing.label()) {
+            debug!("Not fusing crossing of {}", crossing.label());
+            return;
+        }
+
+        let idx = self
+            .crossings
+            .partition_point(|c| c.instant() <= crossing.instant());
+        self.crossings.insert(idx, crossing);
+    }
+
+    /// Emits every event that can no longer change at `now`.
+    ///
+    /// A crossing is decided once its window has fully elapsed. A tag read is
+    /// an orphan once no undecided crossing could still claim it.
+    pub fn flush(&mut self, now: Instant) -> Vec<FusedEvent> {
+        let mut events = Vec::new();
+
+        while let Some(crossing) = self.crossings.front() {
+            if crossing.instant() + self.window > now {
+                break;
+            }
+            let crossing = self.crossings.pop_front().unwrap();
+            events.push(self.fuse(crossing));
+        }
+
+        while let Some(tag) = self.tags.front() {
+            if tag.time() + self.window * 2 > now {
+                break;
+            }
+            let tag = self.tags.pop_front().unwrap();
+            if let Some(event) = self.orphan(tag) {
+                events.push(event);
+            }
+        }
+
+        // A read can only be suppressed by one at most a window older, which
+        // itself expires two windows after it happened.
+        let window = self.window;
+        self.last_orphan.retain(|_, seen| *seen + window * 3 > now);
+
+        events
+    }
+
+    /// Emits every pending event regardless of how recent it is
+    pub fn drain(&mut self) -> Vec<FusedEvent> {
+        let latest = self
+            .crossings
+            .back()
+            .map(|c| c.instant())
+            .into_iter()
+            .chain(self.tags.back().map(|t| t.time()))
+            .max();
+
+        match latest {
+            Some(latest) => self.flush(latest + self.window * 2),
+            None => Vec::new(),
+        }
+    }
+
+    fn fuse(&mut self, crossing: CvDetection) -> FusedEvent {
+        let instant = crossing.instant();
+        let direction = crossing.direction();
+
+        let closest = self
+            .tags
+            .iter()
+            .filter(|t| Self::distance(t.time(), instant) <= self.window)
+            .min_by_key(|t| Self::distance(t.time(), instant))
+            .map(|t| t.tag().to_owned());
+
+        let Some(tag) = closest else {
+            return FusedEvent::Anonymous { direction, instant };
+        };
+
+        // The reader reports the same tag many times while it is in range, all
+        // of those reads belong to this crossing.
+        let window = self.window;
+        self.tags
+            .retain(|t| t.tag() != tag || Self::distance(t.time(), instant) > window);
+
+        debug!("Linked tag {} to crossing", tag);
+        FusedEvent::Person {
+            tag,
+            direction,
+            instant,
+        }
+    }
+
+    fn orphan(&mut self, tag: TagDetection) -> Option<FusedEvent> {
+        let repeated = self
+            .last_orphan
+            .get(tag.tag())
+            .is_some_and(|seen| Self::distance(*seen, tag.time()) <= self.window);
+
+        self.last_orphan.insert(tag.tag().to_owned(), tag.time());
+        if repeated {
+            return None;
+        }
+
+        Some(FusedEvent::TagOnly {
+            tag: tag.tag().to_owned(),
+            instant: tag.time(),
+        })
+    }
+
+    fn distance(a: Instant, b: Instant) -> Duration {
+        if a > b { a - b } else { b - a }
+    }
+}
+
+pub async fn proc_detections(
+    mut rfid_rx: mpsc::Receiver<TagDetection>,
+    mut cv_rx: mpsc::Receiver<CvDetection>,
+    config: FusionConfig,
+    events_tx: mpsc::Sender<FusedEvent>,
+    store: Option<EventStore>,
+    occupancy: Option<Occupancy>,
+) {
+    let window = config.window;
+    let mut engine = FusionEngine::new(config);
+    // A zero period makes `interval` panic
+    let mut tick = interval((window / 4).max(Duration::from_millis(1)));
+    let mut rfid_open = true;
+    let mut cv_open = true;
+
+    info!(
+        "Starting detection fusion (window: {}ms)",
+        window.as_millis()
+    );
+
+    while rfid_open || cv_open {
+        tokio::select! {
+            tag = rfid_rx.recv(), if rfid_open => match tag {
+                Some(tag) => {
+                    if let Some(store) = &store
+                        && let Err(e) = store.record_tag_read(&tag).await
+                    {
+                        warning!("Failed to store tag read: {:#}", e);
+                    }
+                    engine.push_tag(tag);
+                }
+                None => rfid_open = false,
+            },
+            crossing = cv_rx.recv(), if cv_open => match crossing {
+                Some(crossing) => {
+                    if let Some(store) = &store
+                        && let Err(e) = store.record_crossing(&crossing).await
+                    {
+                        warning!("Failed to store crossing: {:#}", e);
+                    }
+                    if let Some(occupancy) = &occupancy
+                        && let Err(e) = occupancy.record(&crossing).await
+                    {
+                        warning!("Failed to count crossing: {:#}", e);
+                    }
+                    engine.push_crossing(crossing);
+                }
+                None => cv_open = false,
+            },
+            _ = tick.tick() => {
+                for event in engine.flush(Instant::now()) {
+                    info!("{}", event);
+                    if events_tx.send(event).await.is_err() {
+                        debug!("Fused event receiver dropped");
+                    }
+                }
+            }
+        }
+    }
+
+    for event in engine.drain() {
+        info!("{}", event);
+        if events_tx.send(event).await.is_err() {
+            debug!("Fused event receiver dropped");
+        }
+    }
+
+    info!("Detection fusion stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opencv::core::Rect;
+
+    fn engine() -> FusionEngine {
+        FusionEngine::new(FusionConfig::from(&FusionConf {
+            window_ms: 1000,
+            ..FusionConf::default()
+        }))
+    }
+
+    fn tag(name: &str, time: Instant) -> TagDetection {
+        TagDetection::new(name.to_owned(), 1, 60, time)
+    }
+
+    #[test]
+    fn test_crossing_linked_to_closest_tag() {
+        let t0 = Instant::now();
+        let mut engine = engine();
+
+        engine.push_tag(tag("A", t0));
+        engine.push_tag(tag("B", t0 + Duration::from_millis(900)));
+        engine.push_tag(tag("B", t0 + Duration::from_millis(1100)));
+        engine.push_crossing(CvDetection::new_with_time(
+            0,
+            "person".into(),
+            Direction::Up,
+            Rect::default(),
+            t0 + Duration::from_millis(1000),
+        ));
+
+        let events = engine.flush(t0 + Duration::from_secs(5));
+        assert_eq!(
+            events,
+            vec![
+                FusedEvent::Person {
+                    tag: "B".into(),
+                    direction: Direction::Up,
+                    instant: t0 + Duration::from_millis(1000),
+                },
+                FusedEvent::TagOnly {
+                    tag: "A".into(),
+                    instant: t0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unmatched_crossing_is_anonymous() {
+        let t0 = Instant::now();
+        let mut engine = engine();
+
+        engine.push_crossing(CvDetection::new_with_time(
+            0,
+            "person".into(),
+            Direction::Down,
+            Rect::default(),
+            t0,
+        ));
+        engine.push_tag(tag("A", t0 + Duration::from_millis(1500)));
+
+        assert!(engine.flush(t0 + Duration::from_millis(500)).is_empty());
+
+        let events = engine.drain();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0],
+            FusedEvent::Anonymous {
+                direction: Direction::Down,
+                instant: t0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_repeated_orphan_reads_reported_once() {
+        let t0 = Instant::now();
+        let mut engine = engine();
+
+        for ms in [0, 300, 600, 900] {
+            engine.push_tag(tag("A", t0 + Duration::from_millis(ms)));
+        }
+
+        let events = engine.drain();
+        assert_eq!(
+            events,
+            vec![FusedEvent::TagOnly {
+                tag: "A".into(),
+                instant: t0,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_crossings_of_other_classes_are_not_fused() {
+        let t0 = Instant::now();
+        let mut engine = engine();
+
+        engine.push_tag(tag("A", t0));
+        engine.push_crossing(CvDetection::new_with_time(
+            0,
+            "cart".into(),
+            Direction::Up,
+            Rect::default(),
+            t0,
+        ));
 
-	
+        assert_eq!(
+            engine.drain(),
+            vec![FusedEvent::TagOnly {
+                tag: "A".into(),
+                instant: t0,
+            }]
+        );
+    }
 }
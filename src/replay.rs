@@ -0,0 +1,245 @@
+use anyhow::{Context, Result};
+use csv::Writer;
+use log::{debug, info, warning};
+use opencv::core::{Mat, MatTraitConst};
+use opencv::videoio::{VideoCapture, VideoCaptureTrait};
+use regex::Regex;
+use serde::Deserialize;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::cv::Tallies;
+use crate::cv::net::Net;
+use crate::direction::Direction;
+use crate::proc::{FusedEvent, FusionConfig, FusionEngine};
+use crate::rfid::parse_line;
+
+#[derive(Debug, Deserialize)]
+struct FrameStamp {
+    frame_number: u64,
+    timestamp: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReadStamp {
+    timestamp: u64,
+    data: String,
+}
+
+/// Timestamps of a session written by [`crate::recorder::SynchronizedRecorder`].
+///
+/// Every timestamp is in nanoseconds since the Unix epoch.
+pub struct Session {
+    /// Capture time of every frame, indexed by frame number
+    frames: Vec<u64>,
+    /// Raw RFID spool lines and the time they were read, oldest first
+    reads: Vec<(u64, String)>,
+}
+
+impl Session {
+    pub fn load(video_timestamps: &Path, detections: &Path) -> Result<Self> {
+        let mut frames = csv::Reader::from_path(video_timestamps)
+            .with_context(|| format!("Failed to open {}", video_timestamps.display()))?
+            .deserialize()
+            .collect::<Result<Vec<FrameStamp>, _>>()
+            .with_context(|| format!("Failed to parse {}", video_timestamps.display()))?;
+        frames.sort_by_key(|f| f.frame_number);
+
+        let mut reads = csv::Reader::from_path(detections)
+            .with_context(|| format!("Failed to open {}", detections.display()))?
+            .deserialize()
+            .collect::<Result<Vec<ReadStamp>, _>>()
+            .with_context(|| format!("Failed to parse {}", detections.display()))?;
+        reads.sort_by_key(|r| r.timestamp);
+
+        Ok(Self {
+            frames: frames.into_iter().map(|f| f.timestamp).collect(),
+            reads: reads.into_iter().map(|r| (r.timestamp, r.data)).collect(),
+        })
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn reads(&self) -> usize {
+        self.reads.len()
+    }
+
+    /// Earliest timestamp in the session
+    fn origin(&self) -> u64 {
+        let first_frame = self.frames.first().copied();
+        let first_read = self.reads.first().map(|(ts, _)| *ts);
+
+        first_frame.into_iter().chain(first_read).min().unwrap_or(0)
+    }
+}
+
+/// Maps recorded timestamps onto [`Instant`]s, keeping their spacing
+struct ReplayClock {
+    base: Instant,
+    origin: u64,
+}
+
+impl ReplayClock {
+    fn instant(&self, timestamp: u64) -> Instant {
+        self.base + Duration::from_nanos(timestamp.saturating_sub(self.origin))
+    }
+
+    fn timestamp(&self, instant: Instant) -> u64 {
+        self.origin + instant.saturating_duration_since(self.base).as_nanos() as u64
+    }
+}
+
+/// Fused events of a replayed session, stamped with recorded timestamps
+pub struct ReplayReport {
+    pub frames: usize,
+    pub events: Vec<(u64, FusedEvent)>,
+    /// Entries and exits of every class, fused or not
+    pub classes: Tallies,
+}
+
+impl ReplayReport {
+    /// Number of people that entered and exited, from the fused events
+    pub fn tally(&self) -> (usize, usize) {
+        let mut tally = (0, 0);
+        for (_, event) in &self.events {
+            match event {
+                FusedEvent::Person { direction, .. } | FusedEvent::Anonymous { direction, .. } => {
+                    match direction {
+                        Direction::Up => tally.0 += 1,
+                        Direction::Down => tally.1 += 1,
+                        Direction::Stationary => {}
+                    }
+                }
+                FusedEvent::TagOnly { .. } => {}
+            }
+        }
+        tally
+    }
+
+    /// Writes every event as `timestamp,event,tag,action`
+    pub fn write_csv(&self, path: &Path) -> Result<()> {
+        let mut writer = Writer::from_path(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        writer.write_record(["timestamp", "event", "tag", "action"])?;
+
+        for (timestamp, event) in &self.events {
+            let (kind, tag, action) = match event {
+                FusedEvent::Person { tag, direction, .. } => {
+                    ("person", tag.as_str(), direction.as_action())
+                }
+                FusedEvent::Anonymous { direction, .. } => ("anonymous", "", direction.as_action()),
+                FusedEvent::TagOnly { tag, .. } => ("tag_only", tag.as_str(), ""),
+            };
+            writer.write_record([timestamp.to_string().as_str(), kind, tag, action])?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Plays a recorded session through the network and the fusion engine.
+///
+/// Frames are processed with their recorded capture time and RFID reads are
+/// injected once the replay reaches the time they were recorded at, so the
+/// same session and settings always give the same events.
+pub struct Replay {
+    session: Session,
+    tag_re: Regex,
+    target_tag: String,
+    fusion: FusionConfig,
+}
+
+impl Replay {
+    pub fn new(session: Session, tag_re: Regex, target_tag: String, fusion: FusionConfig) -> Self {
+        Self {
+            session,
+            tag_re,
+            target_tag,
+            fusion,
+        }
+    }
+
+    pub fn run(self, stream: &mut VideoCapture, net: &mut Net) -> Result<ReplayReport> {
+        let clock = ReplayClock {
+            base: Instant::now(),
+            origin: self.session.origin(),
+        };
+
+        let (cv_tx, mut cv_rx) = mpsc::channel(1024);
+        net.set_detection_sender(cv_tx);
+
+        let mut engine = FusionEngine::new(self.fusion);
+        let mut events = Vec::new();
+        let mut reads = self.session.reads.iter().peekable();
+        let mut frame = Mat::default();
+        let mut frames = 0;
+
+        for &stamp in &self.session.frames {
+            match stream.read(&mut frame) {
+                Ok(true) if !frame.empty() => {}
+                Ok(_) => {
+                    warning!(
+                        "Video ended after {} of {} recorded frames",
+                        frames,
+                        self.session.frames.len()
+                    );
+                    break;
+                }
+                Err(e) => return Err(e).context("Failed to read recorded video"),
+            }
+
+            let now = clock.instant(stamp);
+
+            while let Some((timestamp, line)) = reads.next_if(|(ts, _)| *ts <= stamp) {
+                if let Some(tag) = parse_line(
+                    line,
+                    &self.tag_re,
+                    &self.target_tag,
+                    clock.instant(*timestamp),
+                ) {
+                    engine.push_tag(tag);
+                }
+            }
+
+            if let Err(e) = net.process_frame_at(&frame, now) {
+                warning!("Error while processing frame {}: {}", frames, e);
+            }
+            frames += 1;
+
+            while let Ok(crossing) = cv_rx.try_recv() {
+                debug!("Frame {}: {:?}", frames, crossing);
+                engine.push_crossing(crossing);
+            }
+
+            events.extend(engine.flush(now));
+        }
+
+        // Reads recorded after the last frame can still be orphans
+        for (timestamp, line) in reads {
+            if let Some(tag) = parse_line(
+                line,
+                &self.tag_re,
+                &self.target_tag,
+                clock.instant(*timestamp),
+            ) {
+                engine.push_tag(tag);
+            }
+        }
+        events.extend(engine.drain());
+
+        info!("Replayed {} frames, {} events", frames, events.len());
+
+        Ok(ReplayReport {
+            frames,
+            events: events
+                .into_iter()
+                .map(|event| (clock.timestamp(event.instant()), event))
+                .collect(),
+            classes: net.tallies().clone(),
+        })
+    }
+}
@@ -8,6 +8,7 @@ use std::{
 };
 use tokio::{fs, sync::mpsc, time::sleep};
 
+#[derive(Debug, Clone)]
 pub struct TagDetection {
     tag: String,
     ant: i32,
@@ -20,7 +21,7 @@ struct TagDetections {
 }
 
 impl TagDetection {
-    fn new(tag: String, ant: i32, pot: i32, time: Instant) -> Self {
+    pub fn new(tag: String, ant: i32, pot: i32, time: Instant) -> Self {
         Self {
             tag,
             ant,
@@ -37,6 +38,22 @@ impl TagDetection {
             time: Instant::now(),
         }
     }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn time(&self) -> Instant {
+        self.time
+    }
+
+    pub fn antenna(&self) -> i32 {
+        self.ant
+    }
+
+    pub fn rssi(&self) -> i32 {
+        self.pot
+    }
 }
 
 impl TagDetections {
@@ -47,10 +64,38 @@ impl TagDetections {
     }
 }
 
-async fn process_spool(
-    file: PathBuf,
+/// Parses a raw spool line (`tag,antenna,rssi`) read at `time`.
+///
+/// Returns `None` for malformed lines and tags filtered out by `tag_re` or
+/// `target_tag`.
+pub fn parse_line(
+    line: &str,
     tag_re: &Regex,
     target_tag: &str,
+    time: Instant,
+) -> Option<TagDetection> {
+    let (tag, rest) = line.trim().split_once(',')?;
+
+    if !tag_re.is_match(tag) || !tag.contains(target_tag) {
+        return None;
+    }
+
+    let mut parts = rest.split(',');
+    let ant = parts.next()?.trim().parse::<i32>().ok()?;
+    let rssi = parts
+        .next()?
+        .trim()
+        .trim_start_matches('-')
+        .parse::<i32>()
+        .ok()?;
+
+    Some(TagDetection::new(tag.into(), ant, rssi, time))
+}
+
+pub async fn process_spool(
+    file: PathBuf,
+    tag_re: Regex,
+    target_tag: String,
     cv_tx: mpsc::Sender<TagDetection>,
     rate: f64,
 ) {
@@ -61,32 +106,17 @@ async fn process_spool(
             Ok(c) => c,
             Err(e) => {
                 error!("Error reading file: {}", e);
+                sleep(line_delay).await;
                 continue;
             }
         };
 
         for line in content.lines() {
-            let Some((tag, rest)) = line.split_once(",") else {
-                continue;
-            };
-
-            if !tag_re.is_match(tag) || !tag.contains(target_tag) {
-                continue;
-            }
-
-            let parts: Vec<&str> = rest.split(',').collect();
-
-            let Ok(ant) = parts[0].parse::<i32>() else {
-                continue;
-            };
-            let Ok(rssi) = parts[1].trim_start_matches('-').parse::<i32>() else {
+            let Some(detection) = parse_line(line, &tag_re, &target_tag, Instant::now()) else {
                 continue;
             };
 
-            if let Err(e) = cv_tx
-                .send(TagDetection::new_now(tag.into(), ant, rssi))
-                .await
-            {
+            if let Err(e) = cv_tx.send(detection).await {
                 info!("ALERTA ALERTA ALERTA {}", e);
                 continue;
             };
@@ -0,0 +1,246 @@
+use anyhow::{Context, Result};
+use axum::Json;
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{post, put};
+use log::{debug, error, info, warning};
+use serde::de::DeserializeOwned;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+
+use crate::api::{APIDetectionRequest, APIDetectionResponse, APIUpdateRequest};
+use crate::auth::auth::{
+    API_KEY_HEADER, DEFAULT_SKEW_SECS, KEY_ID_HEADER, NONCE_HEADER, Signature, TIMESTAMP_HEADER,
+    Verifier,
+};
+use crate::auth::keyring::Keyring;
+
+mod store;
+
+pub use store::{Entry, Store};
+
+/// Status and message of a request refused before reaching the store
+type Rejection = (StatusCode, String);
+
+struct AppState {
+    verifier: Verifier,
+    store: Mutex<Store>,
+}
+
+/// Serves the `/api/entry` contract on `bind` until the task is dropped
+pub async fn serve(bind: &str, keyring: Keyring, store: Store) -> Result<()> {
+    let state = Arc::new(AppState {
+        verifier: Verifier::new(keyring, DEFAULT_SKEW_SECS),
+        store: Mutex::new(store),
+    });
+
+    let listener = TcpListener::bind(bind)
+        .await
+        .with_context(|| format!("Failed to listen on {}", bind))?;
+    info!("Serving /api/entry on {}", bind);
+
+    axum::serve(listener, router(state))
+        .await
+        .context("Server stopped")
+}
+
+fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/entry", post(create_entry))
+        .route("/api/entry/{id}", put(update_entry).delete(delete_entry))
+        .with_state(state)
+}
+
+async fn create_entry(
+    State(state): State<Arc<AppState>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Err(rejection) = authenticate(&state, &method, &uri, &headers, &body) {
+        return rejection.into_response();
+    }
+    let request: APIDetectionRequest = match parse(&body) {
+        Ok(request) => request,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let mut store = state.store.lock().unwrap();
+    match store.create(request.event_id, request.person_id, request.action) {
+        Ok(entry) => {
+            debug!("Created entry {}", entry.id);
+            (StatusCode::CREATED, Json(response(entry))).into_response()
+        }
+        Err(e) => internal_error(e),
+    }
+}
+
+async fn update_entry(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u64>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Err(rejection) = authenticate(&state, &method, &uri, &headers, &body) {
+        return rejection.into_response();
+    }
+    let request: APIUpdateRequest = match parse(&body) {
+        Ok(request) => request,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let mut store = state.store.lock().unwrap();
+    match store.update(id, request.action) {
+        Ok(Some(entry)) => {
+            debug!("Updated entry {}", entry.id);
+            Json(response(entry)).into_response()
+        }
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+async fn delete_entry(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u64>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Err(rejection) = authenticate(&state, &method, &uri, &headers, &body) {
+        return rejection.into_response();
+    }
+
+    let mut store = state.store.lock().unwrap();
+    match store.delete(id) {
+        Ok(true) => {
+            debug!("Deleted entry {}", id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+fn authenticate(
+    state: &AppState,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<(), Rejection> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+    let signature = match (
+        header(KEY_ID_HEADER),
+        header(API_KEY_HEADER),
+        header(TIMESTAMP_HEADER).and_then(|t| t.parse().ok()),
+        header(NONCE_HEADER),
+    ) {
+        (Some(key_id), Some(api_key), Some(timestamp), Some(nonce)) => Signature {
+            key_id: key_id.to_owned(),
+            api_key: api_key.to_owned(),
+            timestamp,
+            nonce: nonce.to_owned(),
+        },
+        _ => {
+            return Err((StatusCode::UNAUTHORIZED, "Missing signature headers".into()));
+        }
+    };
+
+    state
+        .verifier
+        .verify_api_key(method.as_str(), uri.path(), body, &signature)
+        .map_err(|e| {
+            warning!("Rejected {} {}: {}", method, uri.path(), e);
+            (StatusCode::UNAUTHORIZED, e.to_string())
+        })
+}
+
+fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, Rejection> {
+    serde_json::from_slice(body).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+fn response(entry: Entry) -> APIDetectionResponse {
+    APIDetectionResponse {
+        id: entry.id.to_string(),
+        person_id: entry.person_id,
+        action: entry.action,
+    }
+}
+
+fn internal_error(e: anyhow::Error) -> Response {
+    error!("Failed to update entries: {:#}", e);
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{Api, ApiError};
+    use crate::conf::ApiConf;
+    use std::collections::BTreeMap;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_client_round_trip() {
+        let dir = std::env::temp_dir().join(format!("vista-server-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let keyring = Keyring::new(
+            "a".into(),
+            BTreeMap::from([("a".to_string(), "alpha".to_string())]),
+        )
+        .unwrap();
+
+        let state = Arc::new(AppState {
+            verifier: Verifier::new(keyring.clone(), DEFAULT_SKEW_SECS),
+            store: Mutex::new(Store::open(&dir.join("entries.json")).unwrap()),
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(axum::serve(listener, router(state)).into_future());
+
+        let conf = ApiConf {
+            base_url,
+            retries: 0,
+            ..ApiConf::default()
+        };
+        let api = Api::new(&conf, keyring).unwrap();
+
+        let request = APIDetectionRequest {
+            event_id: Uuid::new_v4(),
+            person_id: "E200".into(),
+            action: "entered".into(),
+        };
+        let created = api.add_detection(&request).await.unwrap();
+        let again = api.add_detection(&request).await.unwrap();
+        assert_eq!(created.id, again.id);
+
+        let changed = api.change_detection(&created.id, "exited").await.unwrap();
+        assert_eq!(changed.action, "exited");
+
+        api.delete_detection(&created.id).await.unwrap();
+        assert!(matches!(
+            api.delete_detection(&created.id).await,
+            Err(ApiError::NotFound)
+        ));
+
+        let stranger = Keyring::new(
+            "a".into(),
+            BTreeMap::from([("a".to_string(), "other".to_string())]),
+        )
+        .unwrap();
+        let api = Api::new(&conf, stranger).unwrap();
+        assert!(matches!(
+            api.add_detection(&request).await,
+            Err(ApiError::Unauthorized(_))
+        ));
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::{Context, Result};
+use dirs::data_dir;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    pub event_id: Uuid,
+    pub person_id: String,
+    pub action: String,
+    /// Seconds since the Unix epoch
+    pub created_at: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Entries {
+    next_id: u64,
+    entries: BTreeMap<u64, Entry>,
+}
+
+/// Entries kept in a JSON file, rewritten atomically on every change
+pub struct Store {
+    path: PathBuf,
+    data: Entries,
+}
+
+impl Store {
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(data_dir()
+            .context("No data directory for this user")?
+            .join("vista")
+            .join("entries.json"))
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        let data = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Entries::default(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+
+        Ok(Self {
+            path: path.to_owned(),
+            data,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.entries.len()
+    }
+
+    /// Adds an entry, or returns the one already created for `event_id`
+    pub fn create(&mut self, event_id: Uuid, person_id: String, action: String) -> Result<Entry> {
+        if let Some(entry) = self
+            .data
+            .entries
+            .values()
+            .find(|entry| entry.event_id == event_id)
+        {
+            return Ok(entry.clone());
+        }
+
+        self.data.next_id += 1;
+        let entry = Entry {
+            id: self.data.next_id,
+            event_id,
+            person_id,
+            action,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        };
+        self.data.entries.insert(entry.id, entry.clone());
+        self.save()?;
+
+        Ok(entry)
+    }
+
+    /// Changes the action of entry `id`, returning `None` if it does not exist
+    pub fn update(&mut self, id: u64, action: String) -> Result<Option<Entry>> {
+        let Some(entry) = self.data.entries.get_mut(&id) else {
+            return Ok(None);
+        };
+        entry.action = action;
+        let entry = entry.clone();
+        self.save()?;
+
+        Ok(Some(entry))
+    }
+
+    /// Removes entry `id`, returning whether it existed
+    pub fn delete(&mut self, id: u64) -> Result<bool> {
+        if self.data.entries.remove(&id).is_none() {
+            return Ok(false);
+        }
+        self.save()?;
+
+        Ok(true)
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let tmp = self.path.with_extension("json.tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&serde_json::to_vec_pretty(&self.data)?)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace {}", self.path.display()))?;
+
+        Ok(())
+    }
+}